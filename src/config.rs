@@ -0,0 +1,303 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+use toml::Spanned;
+use tracing::{error, info};
+
+use crate::horizon::HorizonWindowConfig;
+use crate::state::{CustomStateDef, Provider};
+use crate::util::*;
+use crate::widgets::{validate_widget, Clock, FromWidgetConfig, WidgetActions};
+use crate::x::ewmh::{WindowAnchor, WindowStackPosition, WindowType};
+use crate::x::strut::StrutConfig;
+use crate::x::x::XSessionContext;
+
+/// Directory under `$XDG_CONFIG_HOME` holding the user config.
+const CONFIG_DIR: &str = "horizon";
+/// Name of the user config file inside [`CONFIG_DIR`].
+const CONFIG_FILE: &str = "config.toml";
+
+/// [Convenience for the default config] default display number is usually 0.
+const DEFAULT_MONITOR: usize = 0;
+/// [Convenience for the default config] default is meaningless.
+const DEFAULT_HEIGHT: i32 = 30;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse config {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    #[error("Invalid config {}, line {line}: {message}", path.display())]
+    Invalid {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+/// The whole user config, one entry per bar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HorizonConfig {
+    pub windows: Vec<HorizonWindowDef>,
+    pub states: Vec<CustomStateDef>,
+}
+
+/// A single `[[window]]` table of the user config.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HorizonWindowDef {
+    pub name: String,
+    #[serde(flatten)]
+    pub config: HorizonWindowConfig,
+    /// The widgets of the window, laid out left to right.
+    #[serde(default)]
+    pub widgets: Vec<WidgetConfig>,
+}
+
+/// A `[[window.widgets]]` table, a node of the widget tree of a window.
+///
+/// Only the `type` is known up front, the other keys are the options of that widget type,
+/// see [`crate::widgets::FromWidgetConfig`].
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WidgetConfig {
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Click, scroll and hover actions, available on every widget.
+    #[serde(default)]
+    pub actions: WidgetActions,
+    #[serde(flatten)]
+    pub options: toml::Table,
+}
+
+/// The config file as written by the user, with the location of each table kept for error reporting.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawConfig {
+    #[serde(default)]
+    window: Vec<Spanned<HorizonWindowDef>>,
+    #[serde(default)]
+    state: Vec<Spanned<CustomStateDef>>,
+}
+
+impl WidgetConfig {
+    /// A widget of type `kind` with its default options.
+    pub fn new(kind: &str) -> Self {
+        Self {
+            kind: kind.to_owned(),
+            actions: WidgetActions::default(),
+            options: toml::Table::new(),
+        }
+    }
+}
+
+/// Location of the user config, `$XDG_CONFIG_HOME/horizon/config.toml`.
+pub fn config_path() -> PathBuf {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .unwrap_or_else(|| {
+            PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(".config")
+        });
+
+    config_home.join(CONFIG_DIR).join(CONFIG_FILE)
+}
+
+impl HorizonConfig {
+    /// Loads the user config, falling back to the built-in bar when there is no usable config file.
+    pub fn load_or_default(x_session: &XSessionContext) -> Self {
+        let path = config_path();
+
+        if !path.exists() {
+            info!("No config found at {}, using the default config", path.display());
+            return Self::default_for(x_session);
+        }
+
+        match Self::load(&path, x_session) {
+            Ok(config) => config,
+            Err(err) => {
+                error!("{err}");
+                Self::default_for(x_session)
+            },
+        }
+    }
+
+    pub fn load(path: &Path, x_session: &XSessionContext) -> Result<Self, ConfigError> {
+        let source = fs::read_to_string(path)
+            .map_err(|source| ConfigError::Io { path: path.to_owned(), source })?;
+
+        Self::parse(path, &source, x_session)
+    }
+
+    pub fn parse(path: &Path, source: &str, x_session: &XSessionContext) -> Result<Self, ConfigError> {
+        let raw: RawConfig = toml::from_str(source)
+            .map_err(|source| ConfigError::Parse { path: path.to_owned(), source })?;
+
+        let invalid = |offset: usize, message: String| ConfigError::Invalid {
+            path: path.to_owned(),
+            line: line_of(source, offset),
+            message,
+        };
+
+        if raw.window.is_empty() {
+            return Err(invalid(0, String::from("At least one [[window]] must be defined")));
+        }
+
+        let mut state_names = HashSet::new();
+        let mut states = Vec::with_capacity(raw.state.len());
+
+        for state in raw.state {
+            let offset = state.span().start;
+            let state = state.into_inner();
+
+            if state.name.is_empty() {
+                return Err(invalid(offset, String::from("State name must not be empty")));
+            }
+
+            if !state_names.insert(state.name.clone()) {
+                return Err(invalid(offset, format!("Duplicate state name '{}'", state.name)));
+            }
+
+            validate_state(&state).map_err(|message| invalid(offset, message))?;
+            states.push(state);
+        }
+
+        let mut names = HashSet::new();
+        let mut windows = Vec::with_capacity(raw.window.len());
+
+        for window in raw.window {
+            let offset = window.span().start;
+            let window = window.into_inner();
+
+            if window.name.is_empty() {
+                return Err(invalid(offset, String::from("Window name must not be empty")));
+            }
+
+            if !names.insert(window.name.clone()) {
+                return Err(invalid(offset, format!("Duplicate window name '{}'", window.name)));
+            }
+
+            window.validate(x_session, &state_names).map_err(|message| invalid(offset, message))?;
+            windows.push(window);
+        }
+
+        Ok(Self { windows, states })
+    }
+
+    /// A single full width bar at the top of the first monitor.
+    pub fn default_for(x_session: &XSessionContext) -> Self {
+        let config = HorizonWindowConfig {
+            screen: DEFAULT_MONITOR,
+            size: Size { width: Length::Fill, height: Length::Pixels(DEFAULT_HEIGHT) },
+            position: Position::default(),
+            margin: Margins::default(),
+            anchor: WindowAnchor::TopLeft,
+            wm_ignore: true,
+            stack_position: WindowStackPosition::Background,
+            window_type: WindowType::Dock,
+            strut: Some(StrutConfig { side: Side::Top, size: Length::Pixels(DEFAULT_HEIGHT), span: None }),
+        };
+
+        Self {
+            windows: vec![
+                HorizonWindowDef {
+                    name: String::from("fullbar"),
+                    config,
+                    widgets: vec![WidgetConfig::new(Clock::NAME)],
+                }
+            ],
+            states: Vec::new(),
+        }
+    }
+}
+
+impl HorizonWindowDef {
+    fn validate(&self, x_session: &XSessionContext, states: &HashSet<String>) -> Result<(), String> {
+        let name = &self.name;
+        let config = &self.config;
+        let monitors = x_session.layout.monitors.len();
+
+        if config.screen >= monitors {
+            return Err(format!(
+                "Window '{name}' is on screen {}, but only {monitors} monitor(s) are connected",
+                config.screen,
+            ));
+        }
+
+        if [config.margin.top, config.margin.right, config.margin.bottom, config.margin.left].contains(&Length::Fill) {
+            return Err(format!("Window '{name}' can not use fill for its margins"));
+        }
+
+        if config.size.width == Length::Fill && config.position.x == Length::Fill
+            || config.size.height == Length::Fill && config.position.y == Length::Fill
+        {
+            return Err(format!("Window '{name}' can not fill both its size and its position on the same axis"));
+        }
+
+        let geometry = config.geometry(&x_session.layout);
+
+        if geometry.width <= 0 || geometry.height <= 0 {
+            return Err(format!("Window '{name}' must have a positive width and height"));
+        }
+
+        if let Some(strut) = &config.strut {
+            let monitor_width = x_session.layout.get_monitor_width(config.screen);
+            let monitor_height = x_session.layout.get_monitor_height(config.screen);
+
+            // Percentages of the thickness and the span are of the monitor across and along the edge.
+            let (thickness, length) = match strut.side {
+                Side::Left | Side::Right => (monitor_width, monitor_height),
+                Side::Top | Side::Bottom => (monitor_height, monitor_width),
+            };
+
+            if strut.size == Length::Fill || strut.size.resolve(thickness, 0) <= 0 {
+                return Err(format!("Window '{name}' has a strut with a non-positive size"));
+            }
+
+            if let Some((start, end)) = strut.span {
+                if start.resolve(length, 0) >= end.resolve(length, 0) {
+                    return Err(format!("Window '{name}' has a strut span that ends before it starts"));
+                }
+            }
+        }
+
+        self.widgets
+            .iter()
+            .try_for_each(|widget| validate_widget(widget, states))
+            .map_err(|message| format!("Window '{name}': {message}"))
+    }
+}
+
+fn validate_state(state: &CustomStateDef) -> Result<(), String> {
+    let name = &state.name;
+
+    if state.provider == Provider::Function {
+        return Err(format!("State '{name}' can not use the function provider, it is reserved for built-in states"));
+    }
+
+    if state.interval == Some(0) {
+        return Err(format!("State '{name}' must have an interval of at least 1 second"));
+    }
+
+    if let Provider::External(external) = &state.provider {
+        if !external.tail && state.interval.is_none() {
+            return Err(format!("State '{name}' runs its command once per interval, so it needs an interval or `tail = true`"));
+        }
+    }
+
+    Ok(())
+}
+
+/// 1-based line number of a byte offset into `source`.
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
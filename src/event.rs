@@ -1,21 +1,50 @@
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::RwLock;
 
-use async_channel::Sender;
+use async_channel::{Receiver, Sender, TrySendError};
 use once_cell::sync::Lazy;
+use tracing::debug;
+
+/// Number of events queued for a listener created by [`EventManager::subscribe`].
+/// Events sent to a listener with a full queue are dropped for that listener.
+const LISTENER_QUEUE_SIZE: usize = 16;
 
 pub static EVENT_MANAGER: Lazy<EventManager> = Lazy::new(|| {
     EventManager::new()
 });
 
-#[derive(Debug, Clone)]
+/// Events published on the [`EVENT_MANAGER`] bus.
+///
+/// Events double as the topics listeners subscribe to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Event {
     HorizonDateTimeUpdated,
+    HorizonAgendaUpdated,
     HorizonWmUpdated,
+    /// A client started, stopped or changed reserving space at the edges of the screen.
+    StrutsUpdated,
+    /// A user defined state was updated, by name.
+    StateUpdated(String),
+    /// Published by a widget [`Action::Event`](crate::widgets::Action::Event), by name.
+    Custom(String),
+}
+
+struct Listener {
+    sender: Sender<Event>,
+    /// The events this listener cares about. Empty means every event.
+    topics: Vec<Event>,
 }
 
 pub struct EventManager {
-    listeners: RwLock<HashMap<usize, Sender<Event>>>,
+    next_index: AtomicUsize,
+    listeners: RwLock<HashMap<usize, Listener>>,
+}
+
+impl Listener {
+    fn wants(&self, event: &Event) -> bool {
+        self.topics.is_empty() || self.topics.contains(event)
+    }
 }
 
 impl EventManager {
@@ -23,19 +52,65 @@ impl EventManager {
         let listeners = RwLock::new(HashMap::new());
 
         Self {
+            next_index: AtomicUsize::new(0),
             listeners
         }
     }
 
+    /// Creates a bounded queue receiving only the given `topics` (or every event if empty).
+    ///
+    /// Returns the listener index to pass to [`EventManager::unregister_listener`].
+    /// Dropping the receiver also unregisters the listener on the next notification.
+    pub fn subscribe(&self, topics: &[Event]) -> (usize, Receiver<Event>) {
+        let (sender, receiver) = async_channel::bounded(LISTENER_QUEUE_SIZE);
+        let index = self.register_listener_for(sender, topics);
+
+        (index, receiver)
+    }
+
+    /// Registers a listener for every event.
     pub fn register_listener(&self, listener: Sender<Event>) -> usize {
-        todo!()
+        self.register_listener_for(listener, &[])
+    }
+
+    /// Registers a listener for the given `topics` (or every event if empty).
+    pub fn register_listener_for(&self, listener: Sender<Event>, topics: &[Event]) -> usize {
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        let listener = Listener {
+            sender: listener,
+            topics: topics.to_vec(),
+        };
+
+        self.listeners.write().unwrap().insert(index, listener);
+        index
     }
 
     pub fn unregister_listener(&self, index: &usize) {
-        todo!()
+        self.listeners.write().unwrap().remove(index);
     }
 
+    /// Sends `event` to every listener subscribed to it, pruning listeners whose receiver is gone.
     pub fn notify_listeners(&self, event: Event) {
-        todo!()
+        let mut closed = Vec::new();
+
+        for (index, listener) in self.listeners.read().unwrap().iter() {
+            if !listener.wants(&event) {
+                continue;
+            }
+
+            match listener.sender.try_send(event.clone()) {
+                Ok(()) => (),
+                Err(TrySendError::Full(event)) => debug!("Listener {index} is full, dropping {event:?}"),
+                Err(TrySendError::Closed(_)) => closed.push(*index),
+            }
+        }
+
+        if !closed.is_empty() {
+            let mut listeners = self.listeners.write().unwrap();
+
+            for index in closed {
+                listeners.remove(&index);
+            }
+        }
     }
 }
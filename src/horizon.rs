@@ -1,30 +1,39 @@
 #![allow(unused_imports)]
 
+use std::cell::RefCell;
 use std::rc::Rc;
 
+use gdk_x11::X11Surface;
+use gio::{Cancellable, FileMonitor, FileMonitorEvent, FileMonitorFlags};
+use glib::clone;
 use gtk::prelude::*;
 use gtk::{Application, Window};
+use serde::Deserialize;
+use tracing::{error, info, warn};
 
+use crate::config::{config_path, HorizonConfig, HorizonWindowDef, WidgetConfig};
+use crate::event::{EVENT_MANAGER, Event};
 use crate::prelude::*;
 use crate::x::ewmh::{WindowAnchor, WindowStackPosition, WindowType};
-use crate::x::strut::{StrutPartialDef, StrutPartialDefBuilder};
-use crate::x::x::XSessionContext;
-
-/// [Convenience for user config] default display number is usually 0.
-const MONITOR: usize = 0;
-/// [Convenience for user config] default is meaningless.
-const HEIGHT: i32 = 30;
+use crate::x::strut::{StrutConfig, StrutPartialDef};
+use crate::x::x::{MonitorLayout, XSessionContext, XWindowContext};
 
 /// User defined window configuration.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct HorizonWindowConfig {
     /// The X display number of the screen the window should be drawn on.
+    #[serde(default)]
     pub screen: usize,
-    /// The desired width and height of the window in pixels.
+    /// The desired width and height of the window.
     pub size: Size,
-    /// A coordinate denoting where on the screen the window should be placed.
+    /// A coordinate denoting where on the screen the window should be placed, inside the margins.
+    #[serde(default)]
     pub position: Position,
+    /// Space kept free between the edges of the monitor and the window.
+    #[serde(default)]
+    pub margin: Margins,
     // TODO:
+    #[serde(default)]
     pub anchor: WindowAnchor,
     /// Whether the window manager should ignore this window.
     ///
@@ -32,66 +41,392 @@ pub struct HorizonWindowConfig {
     ///
     /// - `_NET_WM_STATE_SKIP_PAGER` (Prevents the window from showing up in pagers)
     /// - `_NET_WM_STATE_SKIP_TASKBAR` (Prevents the window from showing up in taskbars)
+    #[serde(default = "default_wm_ignore")]
     pub wm_ignore: bool,
     // TODO:
+    #[serde(default)]
     pub stack_position: WindowStackPosition,
     /// The window type to set for _NET_WM_WINDOW_TYPE.
+    #[serde(default)]
     pub window_type: WindowType,
     /// A reserved space on the screen where no other windows will overlap.
-    pub strut: Option<StrutPartialDef>,
+    #[serde(default)]
+    pub strut: Option<StrutConfig>,
+}
+
+/// Where a window ends up, in pixels relative to the root window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
 }
 
 pub struct HorizonWindow {
     pub name: String,
     pub config: HorizonWindowConfig,
     pub gtk_window: Window,
+    /// The top level container holding the window's widgets.
+    container: gtk::Box,
+    /// The widgets of the window along with the definition they were built from.
+    widgets: Vec<(WidgetConfig, Option<MountedWidget>)>,
+    widget_context: WidgetContext,
+    x_window_context: Option<XWindowContext>,
+}
+
+/// Owns every bar of the application and keeps them in sync with the user config.
+pub struct Horizon {
+    app: Application,
+    x_session: Rc<XSessionContext>,
+    config: RefCell<HorizonConfig>,
+    windows: RefCell<Vec<HorizonWindow>>,
+    config_monitor: RefCell<Option<FileMonitor>>,
+}
+
+fn default_wm_ignore() -> bool {
+    true
+}
+
+impl HorizonWindowConfig {
+    /// Resolves the size, position and margins against the monitor of the window.
+    pub fn geometry(&self, layout: &MonitorLayout) -> WindowGeometry {
+        let (monitor_start_x, monitor_end_x, monitor_start_y, monitor_end_y) = layout.get_monitor_bounds(self.screen);
+        let margin = &self.margin;
+
+        let (x, width) = resolve_axis(monitor_end_x - monitor_start_x, self.position.x, self.size.width, margin.left, margin.right);
+        let (y, height) = resolve_axis(monitor_end_y - monitor_start_y, self.position.y, self.size.height, margin.top, margin.bottom);
+
+        WindowGeometry {
+            x: monitor_start_x + x,
+            y: monitor_start_y + y,
+            width,
+            height,
+        }
+    }
+}
+
+impl Horizon {
+    pub fn new(app: &Application, x_session: Rc<XSessionContext>, config: HorizonConfig) -> Rc<Self> {
+        let mut windows = config.windows
+            .iter()
+            .map(|window_def| HorizonWindow::new(app, window_def, x_session.clone()))
+            .collect::<Vec<_>>();
+
+        for window in windows.iter() {
+            window.present();
+        }
+
+        Self::configure_windows(&x_session, &mut windows);
+
+        Rc::new(Self {
+            app: app.clone(),
+            x_session,
+            config: RefCell::new(config),
+            windows: RefCell::new(windows),
+            config_monitor: RefCell::new(None),
+        })
+    }
+
+    /// Watches the user config and applies any changes to the running windows.
+    pub fn watch_config(self: &Rc<Self>) {
+        let path = config_path();
+        let monitor = match gio::File::for_path(&path).monitor_file(FileMonitorFlags::NONE, None::<&Cancellable>) {
+            Ok(monitor) => monitor,
+            Err(err) => {
+                warn!("Unable to watch {} for changes: {err}", path.display());
+                return;
+            },
+        };
+
+        monitor.connect_changed(clone!(@strong self as horizon => move |_, _, _, event| {
+            match event {
+                FileMonitorEvent::ChangesDoneHint | FileMonitorEvent::Created => horizon.reload(),
+                _ => (),
+            }
+        }));
+
+        self.config_monitor.replace(Some(monitor));
+    }
+
+    /// Moves the windows again whenever other docks change the space they reserve.
+    pub fn watch_struts(self: &Rc<Self>) {
+        let (_, receiver) = EVENT_MANAGER.subscribe(&[Event::StrutsUpdated]);
+        let horizon = Rc::downgrade(self);
+
+        glib::spawn_future_local(async move {
+            while receiver.recv().await.is_ok() {
+                match horizon.upgrade() {
+                    Some(horizon) => Self::configure_windows(&horizon.x_session, &mut horizon.windows.borrow_mut()),
+                    None => break,
+                }
+            }
+        });
+    }
+
+    /// Configures the windows in config order, each one stacked against the struts of other docks
+    /// and of the windows before it.
+    // NOTE: Windows only stack against earlier ones, so two bars on the same edge can not push each other away forever.
+    // Bars of other horizon processes are not stacked against at all, as they would in turn stack against ours.
+    fn configure_windows(x_session: &Rc<XSessionContext>, windows: &mut [HorizonWindow]) {
+        let mut existing = WM.read().unwrap().foreign_struts();
+
+        for window in windows.iter_mut() {
+            window.configure(x_session.clone(), &existing);
+            existing.extend(window.strut());
+        }
+    }
+
+    /// Gives back the space reserved by all windows, before quitting.
+    pub fn clear_struts(&self) {
+        // Unrealized windows already deleted their strut, and no longer have an X window to delete it from.
+        for window in self.windows.borrow().iter().filter(|window| window.gtk_window.is_realized()) {
+            if let Some(x_window_context) = &window.x_window_context {
+                x_window_context.reset_strut_partial_hint(self.x_session.clone());
+            }
+        }
+    }
+
+    /// Re-reads the user config, keeping the current windows if it is invalid.
+    pub fn reload(&self) {
+        let path = config_path();
+        let config = match HorizonConfig::load(&path, &self.x_session) {
+            Ok(config) => config,
+            Err(err) => {
+                error!("{err}");
+                return;
+            },
+        };
+
+        if *self.config.borrow() == config {
+            return;
+        }
+
+        if self.config.borrow().states != config.states {
+            warn!("Changes to [[state]] tables take effect after restarting horizon");
+        }
+
+        info!("Reloading config from {}", path.display());
+        self.apply(&config);
+        self.config.replace(config);
+    }
+
+    fn apply(&self, config: &HorizonConfig) {
+        let mut old_windows = self.windows.take();
+        let mut windows = Vec::with_capacity(config.windows.len());
+
+        for window_def in &config.windows {
+            let window = match old_windows.iter().position(|window| window.name == window_def.name) {
+                Some(index) => {
+                    let mut window = old_windows.remove(index);
+                    window.update(window_def, self.x_session.clone());
+                    window
+                },
+                None => {
+                    let window = HorizonWindow::new(&self.app, window_def, self.x_session.clone());
+                    window.present();
+                    window
+                },
+            };
+
+            windows.push(window);
+        }
+
+        // Whatever is left was removed from the config.
+        for window in old_windows {
+            info!("Removing window {}", window.name);
+            window.gtk_window.destroy();
+        }
+
+        Self::configure_windows(&self.x_session, &mut windows);
+        self.windows.replace(windows);
+    }
 }
 
-pub fn get_windows(horizon: &Application, x_session: Rc<XSessionContext>) -> Vec<HorizonWindow> {
-    // let strut = StrutPartialDef::builder()
-    //     .xsession(x_session.clone())
-    //     .monitor(MONITOR)
-    //     .size(HEIGHT + 5)
-    //     .top(Number::Absolute(HEIGHT))
-    //     .partial_length(30, 80)
-    //     // .full_length(Side::Top)
-    //     .build();
-
-    // dbg!("{}", &strut);
-
-    let strut = StrutPartialDef::default();
-
-    let config = HorizonWindowConfig {
-        screen: MONITOR,
-        size: Size {width: x_session.get_monitor_width(MONITOR), height: HEIGHT},
-        position: Position {x: 0, y: 0},
-        anchor: WindowAnchor::TopLeft,
-        wm_ignore: true,
-        stack_position: WindowStackPosition::Background,
-        window_type: WindowType::Dock,
-        strut: Some(strut),
-    };
-
-    let _box = gtk::Box::builder()
-        .orientation(gtk::Orientation::Horizontal)
-        .build();
-
-    let clock = Clock::new();
-    let clock2 = Clock::new();
-    _box.append(&clock.widget());
-    _box.append(&clock2.widget());
-
-    let gtk_window = Window::builder()
-        .application(horizon)
-        .default_width(config.size.width)
-        .default_height(config.size.height)
-        .resizable(false)
-        .focusable(true)
-        .focus_on_click(true)
-        .child(&_box)
-        .build();
-
-    vec![
-        HorizonWindow {name: String::from("fullbar"), config, gtk_window}
-    ]
+impl HorizonWindow {
+    fn new(horizon: &Application, window_def: &HorizonWindowDef, x_session: Rc<XSessionContext>) -> Self {
+        let config = window_def.config.clone();
+        let geometry = config.geometry(&x_session.layout);
+        let widget_context = WidgetContext::new(x_session.clone(), &config);
+
+        let container = gtk::Box::builder()
+            .orientation(gtk::Orientation::Horizontal)
+            .build();
+
+        // Widgets that fail to build keep their place, so updates can still be matched by index.
+        let widgets = window_def.widgets
+            .iter()
+            .map(|widget| (widget.clone(), MountedWidget::build(&widget_context, widget)))
+            .collect::<Vec<_>>();
+
+        for widget in widgets.iter().filter_map(|(_, widget)| widget.as_ref()) {
+            container.append(widget.root());
+        }
+
+        let gtk_window = Window::builder()
+            .application(horizon)
+            .default_width(geometry.width)
+            .default_height(geometry.height)
+            .resizable(false)
+            .focusable(true)
+            .focus_on_click(true)
+            .child(&container)
+            .build();
+
+        // Gives back the reserved space whenever the window goes away, e.g. on reload or quit.
+        gtk_window.connect_unrealize(move |window| {
+            if let Some(surface) = window.surface().and_downcast::<X11Surface>() {
+                x_session.delete_strut(surface.xid() as u32);
+            }
+        });
+
+        Self {
+            name: window_def.name.clone(),
+            config,
+            gtk_window,
+            container,
+            widgets,
+            widget_context,
+            x_window_context: None,
+        }
+    }
+
+    fn present(&self) {
+        self.gtk_window.present();
+        self.gtk_window.set_title(Some(&format!("Horizon - {}", self.name)));
+        self.gtk_window.set_decorated(false);
+    }
+
+    /// Applies the geometry, struts and hints of the window config to the X window,
+    /// stacked against the `existing` struts of other docks.
+    fn configure(&mut self, x_session: Rc<XSessionContext>, existing: &[StrutPartialDef]) {
+        let x_window_context = XWindowContext::new(x_session.clone(), self, existing);
+        x_window_context.configure_xwindow(x_session.clone(), self);
+        self.x_window_context = Some(x_window_context);
+    }
+
+    /// The strut the window reserves, as last configured.
+    fn strut(&self) -> Option<StrutPartialDef> {
+        self.x_window_context.as_ref()?.strut().cloned()
+    }
+
+    /// Patches the window in place to match a changed definition.
+    fn update(&mut self, window_def: &HorizonWindowDef, x_session: Rc<XSessionContext>) {
+        if self.config != window_def.config {
+            info!("Reconfiguring window {}", self.name);
+
+            if self.config.strut.is_some() && window_def.config.strut.is_none() {
+                if let Some(x_window_context) = &self.x_window_context {
+                    x_window_context.reset_strut_partial_hint(x_session.clone());
+                }
+            }
+
+            self.config = window_def.config.clone();
+            let geometry = self.config.geometry(&x_session.layout);
+            self.gtk_window.set_default_size(geometry.width, geometry.height);
+            // Configured by the Horizon afterwards, along with the windows stacked against this one.
+
+            let widget_context = WidgetContext::new(x_session, &self.config);
+            if self.widget_context != widget_context {
+                // Widgets depending on the window geometry have to be rebuilt.
+                self.widget_context = widget_context;
+                self.widgets.clear();
+                while let Some(child) = self.container.first_child() {
+                    self.container.remove(&child);
+                }
+            }
+        }
+
+        if self.widgets.iter().map(|(widget, _)| widget).ne(window_def.widgets.iter()) {
+            self.update_widgets(&window_def.widgets);
+        }
+    }
+
+    /// Rebuilds only the widgets whose definition changed, keeping the rest (and their state) alive.
+    ///
+    /// Each unchanged widget is reused once, wherever it moved to, so inserting or removing a widget
+    /// leaves the others alone.
+    fn update_widgets(&mut self, widget_defs: &[WidgetConfig]) {
+        let mut old_widgets = std::mem::take(&mut self.widgets)
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<_>>();
+        let mut previous: Option<gtk::Widget> = None;
+
+        for widget_def in widget_defs {
+            let reused = old_widgets
+                .iter_mut()
+                .find(|old| matches!(old, Some((old_def, _)) if old_def == widget_def))
+                .and_then(Option::take);
+
+            let widget = match reused {
+                Some((_, widget)) => {
+                    if let Some(widget) = &widget {
+                        self.container.reorder_child_after(widget.root(), previous.as_ref());
+                    }
+                    widget
+                },
+                None => {
+                    let widget = MountedWidget::build(&self.widget_context, widget_def);
+                    if let Some(widget) = &widget {
+                        self.container.insert_child_after(widget.root(), previous.as_ref());
+                    }
+                    widget
+                },
+            };
+
+            if let Some(widget) = &widget {
+                previous = Some(widget.root().clone());
+            }
+            self.widgets.push((widget_def.clone(), widget));
+        }
+
+        for (_, old_widget) in old_widgets.into_iter().flatten() {
+            if let Some(old_widget) = old_widget {
+                self.container.remove(old_widget.root());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x::x::offset_layout;
+
+    fn window(source: &str) -> HorizonWindowConfig {
+        toml::from_str(source).unwrap()
+    }
+
+    #[test]
+    fn geometry_on_the_primary_monitor() {
+        let config = window(r#"
+            size = { width = "fill", height = 30 }
+        "#);
+
+        assert_eq!(config.geometry(&offset_layout()), WindowGeometry { x: 0, y: 200, width: 1920, height: 30 });
+    }
+
+    #[test]
+    fn geometry_on_the_offset_monitor() {
+        let config = window(r#"
+            screen = 1
+            size = { width = "fill", height = 30 }
+            position = { x = 0, y = "fill" }
+            margin = { left = 8, right = 8, bottom = 4 }
+        "#);
+
+        assert_eq!(config.geometry(&offset_layout()), WindowGeometry { x: 1928, y: 1406, width: 2544, height: 30 });
+    }
+
+    #[test]
+    fn geometry_in_percentages() {
+        let config = window(r#"
+            screen = 1
+            size = { width = "50%", height = "10%" }
+            position = { x = "25%", y = "50%" }
+        "#);
+
+        assert_eq!(config.geometry(&offset_layout()), WindowGeometry { x: 2560, y: 720, width: 1280, height: 144 });
+    }
 }
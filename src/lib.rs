@@ -0,0 +1,13 @@
+#![allow(unused_variables)]
+#![allow(dead_code)]
+
+pub mod contrib;
+pub mod state;
+pub mod widgets;
+pub mod x;
+
+pub mod config;
+pub mod event;
+pub mod horizon;
+pub mod prelude;
+pub mod util;
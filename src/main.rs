@@ -1,29 +1,18 @@
-#![allow(unused_variables)]
-#![allow(dead_code)]
-
-mod contrib;
-mod state;
-mod widgets;
-mod x;
-
-mod event;
-mod horizon;
-mod prelude;
-mod util;
-
 use std::rc::Rc;
-use std::time::Duration;
+use std::sync::{Arc, RwLock};
 
 use gdk_x11::gdk::Display;
 use glib::clone;
 use gtk::prelude::*;
 use gtk::{Application, CssProvider};
-use tokio::{task, time};
-
-use state::*;
-use x::x::{XSessionContext, XWindowContext};
+use nix::sys::signal::Signal;
+use tracing::info;
 
-use event::{EVENT_MANAGER, Event};
+use horizon::config::HorizonConfig;
+use horizon::horizon::Horizon;
+use horizon::state::*;
+use horizon::x;
+use horizon::x::x::XSessionContext;
 
 const APP_ID: &str = "dev.snxwman.horizon";
 
@@ -40,54 +29,65 @@ fn load_css() {
 }
 
 #[doc(hidden)]
-async fn tokio_main() {
-    let forever = task::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(1));
-
-        loop {
-            interval.tick().await;
-            DATETIME.write().unwrap().update();
-            let _ = DATETIME.write().unwrap().sender.send(ChannelMessage::Updated);
-        }
-    })
-    .await;
+async fn tokio_main(custom_states: Vec<Arc<RwLock<CustomState>>>) {
+    let mut states = vec![
+        spawn_state(DATETIME.clone()),
+        spawn_state(AGENDA.clone()),
+    ];
+    states.extend(custom_states.into_iter().map(spawn_state));
+
+    for state in states {
+        let _ = state.await;
+    }
 }
 
 #[doc(hidden)]
 fn gtk_main(app: &Application) {
+    // Get the xsession and config for the whole application.
+    // The xsession should be constant for the whole application duration,
+    // while the windows follow any changes made to the config.
+    let x_session_context = Rc::new(XSessionContext::new());
+    let config = HorizonConfig::load_or_default(&x_session_context);
+
+    x::watch::spawn_wm_watcher();
+
+    let custom_states = config.states
+        .iter()
+        .map(CustomState::register)
+        .collect::<Vec<_>>();
+
+    // Widgets are built first, as they decide how often some states refresh.
+    let horizon = Horizon::new(app, x_session_context.clone(), config);
+    horizon.watch_config();
+    horizon.watch_struts();
+
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .unwrap();
 
     std::thread::spawn(move || {
-        rt.block_on(tokio_main());
+        rt.block_on(tokio_main(custom_states));
     });
 
-    // Get the xsession and config for the whole application.
-    // These should be constant for the whole application duration.
-    let x_session_context = Rc::new(XSessionContext::new());
-    let horizon_windows = horizon::get_windows(app, x_session_context.clone());
-
-    // Build up the window definitions from the user config.
-    for horizon_window in horizon_windows {
-        horizon_window.gtk_window.present();
-
-        let x_window_context = XWindowContext::new(x_session_context.clone(), &horizon_window);
-        x_window_context.configure_xwindow(x_session_context.clone(), &horizon_window);
-        horizon_window.gtk_window.set_title(Some(&format!("Horizon - {}", horizon_window.name)));
-        horizon_window.gtk_window.set_decorated(false);
-
-        // window
-        //     .window
-        //     .connect_unrealize(clone!(@strong x_session_context => move |_| {
-        //         x_window_context.reset_strut_partial_hint(x_session_context.clone());
-        //     }));
+    // Struts are also deleted as each window unrealizes, but the windows may outlive the main loop.
+    app.connect_shutdown(clone!(@strong horizon => move |_| horizon.clear_struts()));
+
+    for signal in [Signal::SIGINT, Signal::SIGTERM, Signal::SIGHUP] {
+        glib::unix_signal_add_local(signal as i32, clone!(@weak app => @default-return glib::ControlFlow::Break, move || {
+            info!("Received {}, quitting", signal.as_str());
+            app.quit();
+            glib::ControlFlow::Break
+        }));
     }
 }
 
 #[doc(hidden)]
 fn main() {
+    tracing_subscriber::fmt::init();
+    // GTK runs on the main thread.
+    x::x::clear_struts_on_panic(std::thread::current().id());
+
     let app = Application::builder()
         .application_id(APP_ID)
         .build();
@@ -0,0 +1,909 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::prelude::*;
+use chrono::{Days, Months, TimeDelta};
+use chrono_tz::Tz;
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use ical::IcalParser;
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use tokio::sync::watch;
+use tokio::sync::watch::Sender;
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+use crate::event::Event;
+use crate::util::expand_home;
+use super::{ChannelMessage, HorizonState, Loader, Provider, Refresh, StateError};
+
+pub static AGENDA: Lazy<Arc<RwLock<HorizonAgenda>>> = Lazy::new(|| {
+    Arc::new(RwLock::new(HorizonAgenda::new()))
+});
+
+/// How far ahead of today recurring events are expanded.
+const LOOKAHEAD_DAYS: u64 = 60;
+/// How often the calendars are read again.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+/// Upper bound on the periods a single recurrence rule is expanded for, in case of a huge or broken rule.
+const MAX_PERIODS: u32 = 100_000;
+/// Name of the file holding the name of a vdir collection, as written by vdirsyncer.
+const DISPLAYNAME_FILE: &str = "displayname";
+
+/// Events of local iCalendar files, from the start of today until [`LOOKAHEAD_DAYS`] ahead.
+#[derive(Debug)]
+pub struct HorizonAgenda {
+    /// Sorted by start, recurring events appear once per occurrence.
+    pub events: Vec<AgendaEvent>,
+    pub provider: Provider,
+    pub sender: Sender<ChannelMessage>,
+    /// `.ics` files and vdir directories to read, with the number of widgets using each.
+    sources: HashMap<PathBuf, usize>,
+    /// Wakes the refresh loop when sources are added or removed.
+    refresh_now: Arc<Notify>,
+}
+
+/// A single occurrence of a calendar event.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct AgendaEvent {
+    pub summary: String,
+    pub location: Option<String>,
+    /// The name of the file or vdir collection the event comes from.
+    pub calendar: String,
+    pub start: DateTime<Local>,
+    /// Exclusive, all-day events end at midnight after their last day.
+    pub end: DateTime<Local>,
+    pub all_day: bool,
+}
+
+/// How a DTSTART, DTEND or similar value maps to an instant.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TimeKind {
+    /// No timezone, the time is the same wherever the user is. Also used for all-day events.
+    Floating,
+    Utc,
+    Zoned(Tz),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct EventTime {
+    naive: NaiveDateTime,
+    kind: TimeKind,
+    all_day: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The parts of an RRULE horizon understands.
+// TODO: BYSETPOS, BYWEEKNO, BYYEARDAY, WKST and the sub-daily frequencies are not supported.
+#[derive(Debug, Clone, PartialEq)]
+struct RecurrenceRule {
+    frequency: Frequency,
+    interval: u32,
+    count: Option<u32>,
+    until: Option<EventTime>,
+    /// Weekdays, optionally the n-th one of the month, e.g. `2MO` or `-1FR`.
+    by_day: Vec<(Option<i32>, Weekday)>,
+    by_month_day: Vec<i32>,
+    by_month: Vec<u32>,
+}
+
+impl HorizonAgenda {
+    pub fn new() -> Self {
+        let (sender, receiver) = watch::channel(ChannelMessage::Init);
+
+        Self {
+            events: Vec::new(),
+            provider: Provider::Function,
+            sender,
+            sources: HashMap::new(),
+            refresh_now: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Reads `calendars` until the matching [`HorizonAgenda::remove_sources`], starting right away.
+    ///
+    /// Calendars that do not exist yet, e.g. before the first sync, are read once they do.
+    pub fn add_sources(&mut self, calendars: &[String]) {
+        let mut added = false;
+
+        for calendar in calendars {
+            let path = expand_home(calendar);
+
+            if !path.exists() {
+                warn!("Calendar {} does not exist, skipping it until it does", path.display());
+            }
+
+            let users = self.sources.entry(path).or_default();
+            added |= *users == 0;
+            *users += 1;
+        }
+
+        if added {
+            self.refresh_now.notify_one();
+        }
+    }
+
+    pub fn remove_sources(&mut self, calendars: &[String]) {
+        let mut removed = false;
+
+        for calendar in calendars {
+            let path = expand_home(calendar);
+
+            if let Some(users) = self.sources.get_mut(&path) {
+                *users -= 1;
+
+                if *users == 0 {
+                    self.sources.remove(&path);
+                    removed = true;
+                }
+            }
+        }
+
+        if removed {
+            self.refresh_now.notify_one();
+        }
+    }
+
+    /// Events that have not ended by `now`, starting before `until`.
+    pub fn upcoming(&self, now: DateTime<Local>, until: DateTime<Local>) -> impl Iterator<Item = &AgendaEvent> {
+        self.events
+            .iter()
+            .filter(move |event| event.end > now && event.start < until)
+    }
+
+    /// Whether any event takes place on `date`.
+    pub fn has_events_on(&self, date: NaiveDate) -> bool {
+        self.events.iter().any(|event| event.occurs_on(date))
+    }
+}
+
+impl HorizonState for HorizonAgenda {
+    type Data = Vec<AgendaEvent>;
+
+    fn provider(&self) -> Provider {
+        self.provider.clone()
+    }
+
+    fn refresh_mode(&self) -> Refresh {
+        Refresh::Interval(REFRESH_INTERVAL)
+    }
+
+    fn refresh(&mut self) -> Result<(), StateError> {
+        // Never called, the calendars are read by the loader without holding the state lock.
+        Ok(())
+    }
+
+    fn loader(&self) -> Option<Loader<Vec<AgendaEvent>>> {
+        let sources = self.sources.keys().cloned().collect::<Vec<_>>();
+        Some(Box::new(move || Ok(read_sources(&sources))))
+    }
+
+    fn refresh_now(&self) -> Option<Arc<Notify>> {
+        Some(self.refresh_now.clone())
+    }
+
+    fn apply(&mut self, data: Vec<AgendaEvent>) {
+        self.events = data;
+    }
+
+    fn event(&self) -> Event {
+        Event::HorizonAgendaUpdated
+    }
+
+    fn sender(&self) -> &Sender<ChannelMessage> {
+        &self.sender
+    }
+}
+
+impl AgendaEvent {
+    pub fn occurs_on(&self, date: NaiveDate) -> bool {
+        self.start < local_midnight(date + Days::new(1)) && self.end > local_midnight(date)
+    }
+}
+
+impl Default for HorizonAgenda {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The events of all `sources`, from the start of today until [`LOOKAHEAD_DAYS`] ahead, sorted by start.
+fn read_sources<'a>(sources: impl IntoIterator<Item = &'a PathBuf>) -> Vec<AgendaEvent> {
+    let today = Local::now().date_naive();
+    let window = (
+        local_midnight(today),
+        local_midnight(today + Days::new(LOOKAHEAD_DAYS)),
+    );
+
+    let mut events = Vec::new();
+
+    for path in sources {
+        read_source(path, window, &mut events);
+    }
+
+    events.sort_by(|a, b| (a.start, &a.summary).cmp(&(b.start, &b.summary)));
+    events
+}
+
+/// Reads the events of an `.ics` file, or of a vdir collection or a directory of them.
+fn read_source(path: &Path, window: (DateTime<Local>, DateTime<Local>), events: &mut Vec<AgendaEvent>) {
+    // Warned about when added.
+    if !path.exists() {
+        return;
+    }
+
+    if !path.is_dir() {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        read_file(path, &name, window, events);
+        return;
+    }
+
+    // vdirsyncer keeps one directory per collection, each holding one `.ics` file per event.
+    let collections = fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir());
+
+    for collection in std::iter::once(path.to_owned()).chain(collections) {
+        let name = fs::read_to_string(collection.join(DISPLAYNAME_FILE))
+            .map(|name| name.trim().to_owned())
+            .ok()
+            .filter(|name| !name.is_empty())
+            .unwrap_or_else(|| collection.file_name().unwrap_or_default().to_string_lossy().into_owned());
+
+        let files = match fs::read_dir(&collection) {
+            Ok(files) => files,
+            Err(source) => {
+                warn!("{}", StateError::Io { path: collection, source });
+                continue;
+            },
+        };
+
+        for file in files.flatten().map(|entry| entry.path()) {
+            if file.is_file() && file.extension().is_some_and(|extension| extension == "ics") {
+                read_file(&file, &name, window, events);
+            }
+        }
+    }
+}
+
+fn read_file(path: &Path, calendar: &str, window: (DateTime<Local>, DateTime<Local>), events: &mut Vec<AgendaEvent>) {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(source) => {
+            warn!("{}", StateError::Io { path: path.to_owned(), source });
+            return;
+        },
+    };
+
+    let mut components = Vec::new();
+
+    for ical in IcalParser::new(BufReader::new(file)) {
+        match ical {
+            Ok(ical) => components.extend(ical.events),
+            Err(source) => {
+                warn!("{}", StateError::Ical { path: path.to_owned(), source });
+                return;
+            },
+        }
+    }
+
+    // Occurrences of a recurring event that were moved or changed, by UID.
+    let mut overridden = HashMap::<String, HashSet<DateTime<Local>>>::new();
+
+    for component in &components {
+        let (Some(uid), Some(recurrence_id)) = (value(component, "UID"), property(component, "RECURRENCE-ID")) else {
+            continue;
+        };
+
+        if let Some(instant) = parse_time(recurrence_id, None).and_then(|time| time.resolve()) {
+            overridden.entry(uid.to_owned()).or_default().insert(instant);
+        }
+    }
+
+    for component in &components {
+        let overrides = value(component, "UID")
+            .filter(|_| property(component, "RECURRENCE-ID").is_none())
+            .and_then(|uid| overridden.get(uid));
+
+        if let Err(err) = expand_event(component, calendar, overrides, window, events) {
+            debug!("Skipping an event of {}: {err}", path.display());
+        }
+    }
+}
+
+/// Adds the occurrences of `component` that overlap `window` to `events`.
+fn expand_event(
+    component: &IcalEvent,
+    calendar: &str,
+    overridden: Option<&HashSet<DateTime<Local>>>,
+    window: (DateTime<Local>, DateTime<Local>),
+    events: &mut Vec<AgendaEvent>,
+) -> Result<(), String> {
+    if value(component, "STATUS").is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED")) {
+        return Ok(());
+    }
+
+    let start = property(component, "DTSTART")
+        .and_then(|start| parse_time(start, None))
+        .ok_or("missing or invalid DTSTART")?;
+
+    let length = match (property(component, "DTEND"), value(component, "DURATION")) {
+        (Some(end), _) => {
+            let end = parse_time(end, Some(start.kind)).ok_or("invalid DTEND")?;
+
+            // Kept in wall time where possible, so recurring events keep their length across DST changes.
+            match (end.kind == start.kind, start.resolve(), end.resolve()) {
+                (false, Some(begin), Some(end)) => end - begin,
+                _ => end.naive - start.naive,
+            }
+        },
+        (None, Some(duration)) => parse_duration(duration).ok_or("invalid DURATION")?,
+        (None, None) if start.all_day => TimeDelta::days(1),
+        (None, None) => TimeDelta::zero(),
+    };
+
+    let excluded = component.properties
+        .iter()
+        .filter(|property| property.name.eq_ignore_ascii_case("EXDATE"))
+        .flat_map(|exdate| split_list(exdate, start.kind))
+        .filter_map(|time| time.resolve())
+        .chain(overridden.into_iter().flatten().copied())
+        .collect::<HashSet<_>>();
+
+    let summary = value(component, "SUMMARY").map(unescape).unwrap_or_default();
+    let location = value(component, "LOCATION").map(unescape).filter(|location| !location.is_empty());
+
+    let mut push = |occurrence: EventTime| {
+        let (Some(begin), Some(end)) = (occurrence.resolve(), occurrence.shifted(length).resolve()) else {
+            return;
+        };
+
+        if end > window.0 && begin < window.1 && !excluded.contains(&begin) {
+            events.push(AgendaEvent {
+                summary: summary.clone(),
+                location: location.clone(),
+                calendar: calendar.to_owned(),
+                start: begin,
+                end: end.max(begin),
+                all_day: start.all_day,
+            });
+        }
+    };
+
+    let Some(rule) = value(component, "RRULE") else {
+        push(start);
+        return Ok(());
+    };
+
+    let rule = match RecurrenceRule::parse(rule, start.kind) {
+        Ok(rule) => rule,
+        Err(err) => {
+            // The event still happens at least once.
+            push(start);
+            return Err(err);
+        },
+    };
+
+    // An UNTIL date includes the whole day.
+    let until = rule.until
+        .map(|until| match until.all_day {
+            true => until.shifted(TimeDelta::days(1) - TimeDelta::seconds(1)),
+            false => until,
+        })
+        .and_then(|until| until.resolve());
+    let mut count = 0;
+
+    rule.expand(start.naive, |naive| {
+        let occurrence = EventTime { naive, ..start };
+        let Some(instant) = occurrence.resolve() else {
+            return true;
+        };
+
+        if instant >= window.1 || until.is_some_and(|until| instant > until) || rule.count.is_some_and(|max| count >= max) {
+            return false;
+        }
+
+        count += 1;
+        push(occurrence);
+        true
+    });
+
+    // RDATE adds single occurrences next to, or instead of, the rule.
+    for rdate in component.properties.iter().filter(|property| property.name.eq_ignore_ascii_case("RDATE")) {
+        split_list(rdate, start.kind).into_iter().for_each(&mut push);
+    }
+
+    Ok(())
+}
+
+impl EventTime {
+    /// The instant in the local timezone. Times skipped by a daylight saving time change move forward an hour.
+    fn resolve(&self) -> Option<DateTime<Local>> {
+        let in_zone = |naive: &NaiveDateTime| match self.kind {
+            TimeKind::Floating => Local.from_local_datetime(naive).earliest(),
+            TimeKind::Utc => Some(Utc.from_utc_datetime(naive).with_timezone(&Local)),
+            TimeKind::Zoned(tz) => tz.from_local_datetime(naive).earliest().map(|time| time.with_timezone(&Local)),
+        };
+
+        in_zone(&self.naive).or_else(|| in_zone(&(self.naive + TimeDelta::hours(1))))
+    }
+
+    fn shifted(&self, delta: TimeDelta) -> Self {
+        Self { naive: self.naive + delta, ..*self }
+    }
+}
+
+impl RecurrenceRule {
+    /// Parses an RRULE value, e.g. `FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,WE`.
+    fn parse(rule: &str, kind: TimeKind) -> Result<Self, String> {
+        let mut frequency = None;
+        let mut parsed = Self {
+            frequency: Frequency::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+        };
+
+        for part in rule.split(';').filter(|part| !part.is_empty()) {
+            let (key, value) = part.split_once('=').ok_or_else(|| format!("invalid RRULE part '{part}'"))?;
+            let invalid = || format!("invalid RRULE {key} '{value}'");
+            let list = || value.split(',');
+
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => frequency = Some(match value.to_ascii_uppercase().as_str() {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return Err(format!("unsupported RRULE frequency '{value}'")),
+                }),
+                "INTERVAL" => parsed.interval = value.parse().ok().filter(|interval| *interval > 0).ok_or_else(invalid)?,
+                "COUNT" => parsed.count = Some(value.parse().map_err(|_| invalid())?),
+                "UNTIL" => parsed.until = Some(parse_value(value, kind).ok_or_else(invalid)?),
+                "BYDAY" => parsed.by_day = list().map(parse_weekday).collect::<Option<_>>().ok_or_else(invalid)?,
+                "BYMONTHDAY" => parsed.by_month_day = list().map(str::parse).collect::<Result<_, _>>().map_err(|_| invalid())?,
+                "BYMONTH" => parsed.by_month = list().map(str::parse).collect::<Result<_, _>>().map_err(|_| invalid())?,
+                "WKST" => (),
+                _ => return Err(format!("unsupported RRULE part '{key}'")),
+            }
+        }
+
+        parsed.frequency = frequency.ok_or("RRULE without FREQ")?;
+        Ok(parsed)
+    }
+
+    /// Calls `visit` with every occurrence from `start` on, in order, until it returns false.
+    fn expand(&self, start: NaiveDateTime, mut visit: impl FnMut(NaiveDateTime) -> bool) {
+        for period in 0..MAX_PERIODS {
+            let Some(mut dates) = self.dates_in_period(start.date(), period) else {
+                return;
+            };
+
+            dates.sort();
+            dates.dedup();
+
+            for date in dates {
+                let occurrence = date.and_time(start.time());
+
+                if occurrence >= start && !visit(occurrence) {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// The dates of the `period`-th day, week, month or year of the rule, `None` past the supported range.
+    fn dates_in_period(&self, start: NaiveDate, period: u32) -> Option<Vec<NaiveDate>> {
+        let step = period.checked_mul(self.interval)?;
+        let in_month = |date: &NaiveDate| self.by_month.is_empty() || self.by_month.contains(&date.month());
+
+        let dates = match self.frequency {
+            Frequency::Daily => {
+                let date = start.checked_add_days(Days::new(step as u64))?;
+                let by_day = self.by_day.is_empty() || self.by_day.iter().any(|(_, weekday)| *weekday == date.weekday());
+                let by_month_day = self.by_month_day.is_empty() || self.month_days(date).contains(&date);
+
+                [date].into_iter().filter(|_| by_day && by_month_day).collect()
+            },
+            Frequency::Weekly => {
+                let monday = start.week(Weekday::Mon).first_day().checked_add_days(Days::new(step as u64 * 7))?;
+                let weekdays = match self.by_day.is_empty() {
+                    true => vec![start.weekday()],
+                    false => self.by_day.iter().map(|(_, weekday)| *weekday).collect(),
+                };
+
+                weekdays
+                    .into_iter()
+                    .map(|weekday| monday + Days::new(weekday.num_days_from_monday() as u64))
+                    .collect()
+            },
+            Frequency::Monthly => {
+                let first = start.with_day(1)?.checked_add_months(Months::new(step))?;
+                self.days_of_month(first, start.day())
+            },
+            Frequency::Yearly => {
+                let year = start.year().checked_add(step as i32)?;
+                let months = match self.by_month.is_empty() {
+                    true => vec![start.month()],
+                    false => self.by_month.clone(),
+                };
+
+                months
+                    .into_iter()
+                    .filter_map(|month| NaiveDate::from_ymd_opt(year, month, 1))
+                    .flat_map(|first| self.days_of_month(first, start.day()))
+                    .collect()
+            },
+        };
+
+        Some(dates.into_iter().filter(in_month).collect())
+    }
+
+    /// The dates of the month starting on `first` matching BYMONTHDAY and BYDAY, or `day` if neither is set.
+    fn days_of_month(&self, first: NaiveDate, day: u32) -> Vec<NaiveDate> {
+        if !self.by_month_day.is_empty() {
+            return self.month_days(first)
+                .into_iter()
+                .filter(|date| self.by_day.is_empty() || self.by_day.iter().any(|(_, weekday)| *weekday == date.weekday()))
+                .collect();
+        }
+
+        if self.by_day.is_empty() {
+            // Months without that day are skipped, e.g. the 31st in April.
+            return first.with_day(day).into_iter().collect();
+        }
+
+        let days = first.iter_days().take_while(|date| date.month() == first.month()).collect::<Vec<_>>();
+
+        self.by_day
+            .iter()
+            .flat_map(|(ordinal, weekday)| {
+                let matching = days.iter().copied().filter(|date| date.weekday() == *weekday).collect::<Vec<_>>();
+
+                match ordinal {
+                    Some(n) if *n > 0 => matching.get(*n as usize - 1).copied().into_iter().collect(),
+                    Some(n) => matching.len().checked_sub(n.unsigned_abs() as usize).and_then(|i| matching.get(i).copied()).into_iter().collect(),
+                    None => matching,
+                }
+            })
+            .collect()
+    }
+
+    /// The BYMONTHDAY dates of the month of `date`, negative days count from the end of the month.
+    fn month_days(&self, date: NaiveDate) -> Vec<NaiveDate> {
+        let Some(first) = date.with_day(1) else {
+            return Vec::new();
+        };
+        let length = (first + Months::new(1) - first).num_days() as i32;
+
+        self.by_month_day
+            .iter()
+            .map(|day| if *day < 0 { length + day + 1 } else { *day })
+            .filter(|day| (1..=length).contains(day))
+            .filter_map(|day| first.with_day(day as u32))
+            .collect()
+    }
+}
+
+fn property<'a>(component: &'a IcalEvent, name: &str) -> Option<&'a Property> {
+    component.properties.iter().find(|property| property.name.eq_ignore_ascii_case(name))
+}
+
+fn value<'a>(component: &'a IcalEvent, name: &str) -> Option<&'a str> {
+    property(component, name).and_then(|property| property.value.as_deref())
+}
+
+fn param<'a>(property: &'a Property, name: &str) -> Option<&'a str> {
+    property.params
+        .iter()
+        .flatten()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+}
+
+/// Parses a date or date-time property, using its TZID, else `default` for times without a `Z` suffix.
+fn parse_time(property: &Property, default: Option<TimeKind>) -> Option<EventTime> {
+    let kind = match param(property, "TZID") {
+        Some(tzid) => match tzid.parse::<Tz>() {
+            Ok(tz) => TimeKind::Zoned(tz),
+            Err(_) => {
+                // NOTE: VTIMEZONE definitions are not read, so only IANA names are understood.
+                debug!("Unknown timezone '{tzid}', using local time");
+                TimeKind::Floating
+            },
+        },
+        None => default.unwrap_or(TimeKind::Floating),
+    };
+
+    parse_value(property.value.as_deref()?.split(',').next()?, kind)
+}
+
+/// The times of a list property such as EXDATE or RDATE.
+fn split_list(property: &Property, default: TimeKind) -> Vec<EventTime> {
+    let Some(first) = parse_time(property, Some(default)) else {
+        return Vec::new();
+    };
+
+    property.value
+        .iter()
+        .flat_map(|value| value.split(','))
+        .filter_map(|value| parse_value(value, first.kind))
+        .collect()
+}
+
+/// Parses `20240131` or `20240131T093000`, with an optional `Z` for UTC.
+fn parse_value(value: &str, kind: TimeKind) -> Option<EventTime> {
+    let value = value.trim();
+
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y%m%d") {
+        return Some(EventTime { naive: date.and_time(NaiveTime::MIN), kind: TimeKind::Floating, all_day: true });
+    }
+
+    let (value, kind) = match value.strip_suffix('Z') {
+        Some(value) => (value, TimeKind::Utc),
+        None => (value, kind),
+    };
+
+    let naive = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    Some(EventTime { naive, kind, all_day: false })
+}
+
+/// Parses a DURATION value, e.g. `PT1H30M` or `P1D`.
+fn parse_duration(value: &str) -> Option<TimeDelta> {
+    let (sign, value) = match value.strip_prefix('-') {
+        Some(value) => (-1, value),
+        None => (1, value.strip_prefix('+').unwrap_or(value)),
+    };
+
+    let mut total = TimeDelta::zero();
+    let mut number = String::new();
+
+    for c in value.strip_prefix('P')?.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            },
+            'T' => continue,
+            'W' => TimeDelta::weeks(1),
+            'D' => TimeDelta::days(1),
+            'H' => TimeDelta::hours(1),
+            'M' => TimeDelta::minutes(1),
+            'S' => TimeDelta::seconds(1),
+            _ => return None,
+        };
+
+        total += unit * number.parse::<i32>().ok()?;
+        number.clear();
+    }
+
+    Some(total * sign)
+}
+
+/// Parses a BYDAY entry, e.g. `MO`, `2TU` or `-1FR`.
+fn parse_weekday(value: &str) -> Option<(Option<i32>, Weekday)> {
+    let split = value.len().checked_sub(2)?;
+    let (ordinal, weekday) = value.split_at(split);
+
+    let weekday = match weekday.to_ascii_uppercase().as_str() {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    };
+
+    let ordinal = match ordinal {
+        "" => None,
+        ordinal => Some(ordinal.trim_start_matches('+').parse().ok().filter(|n| *n != 0)?),
+    };
+
+    Some((ordinal, weekday))
+}
+
+/// Undoes the escaping of iCalendar TEXT values.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n' | 'N') => unescaped.push('\n'),
+                Some(escaped) => unescaped.push(escaped),
+                None => (),
+            },
+            (c, false) => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// The start of `date` in the local timezone.
+pub fn local_midnight(date: NaiveDate) -> DateTime<Local> {
+    EventTime { naive: date.and_time(NaiveTime::MIN), kind: TimeKind::Floating, all_day: true }
+        .resolve()
+        .unwrap_or_else(Local::now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/agenda").join(name)
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        date(year, month, day).and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    /// The events of a fixture between the starts of `from` and `until`, sorted by start.
+    fn read(name: &str, from: NaiveDate, until: NaiveDate) -> Vec<AgendaEvent> {
+        let mut events = Vec::new();
+        read_file(&fixture(name), "Test", (local_midnight(from), local_midnight(until)), &mut events);
+
+        events.sort_by_key(|event| event.start);
+        events
+    }
+
+    /// Floating times are in the local timezone, so the local wall times match in any timezone.
+    fn starts(events: &[AgendaEvent]) -> Vec<NaiveDateTime> {
+        events.iter().map(|event| event.start.naive_local()).collect()
+    }
+
+    #[test]
+    fn weekly_by_day() {
+        let events = read("weekly_byday.ics", date(2024, 1, 1), date(2024, 1, 15));
+
+        assert_eq!(starts(&events), [
+            at(2024, 1, 1, 9, 0), at(2024, 1, 3, 9, 0), at(2024, 1, 5, 9, 0),
+            at(2024, 1, 8, 9, 0), at(2024, 1, 10, 9, 0), at(2024, 1, 12, 9, 0),
+        ]);
+        assert!(events.iter().all(|event| event.end - event.start == TimeDelta::minutes(30)));
+    }
+
+    #[test]
+    fn monthly_last_friday() {
+        let events = read("monthly_last_friday.ics", date(2024, 1, 1), date(2024, 5, 1));
+
+        assert_eq!(starts(&events), [
+            at(2024, 1, 26, 17, 0), at(2024, 2, 23, 17, 0), at(2024, 3, 29, 17, 0), at(2024, 4, 26, 17, 0),
+        ]);
+        assert!(events.iter().all(|event| event.end - event.start == TimeDelta::hours(1)));
+    }
+
+    #[test]
+    fn monthly_31st_skips_short_months() {
+        let events = read("monthly_31st.ics", date(2024, 1, 1), date(2024, 8, 1));
+
+        assert_eq!(starts(&events), [
+            at(2024, 1, 31, 12, 0), at(2024, 3, 31, 12, 0), at(2024, 5, 31, 12, 0), at(2024, 7, 31, 12, 0),
+        ]);
+    }
+
+    #[test]
+    fn count_includes_excluded_dates() {
+        let events = read("count_exdate.ics", date(2024, 1, 1), date(2024, 2, 1));
+
+        assert_eq!(starts(&events), [
+            at(2024, 1, 1, 8, 0), at(2024, 1, 2, 8, 0), at(2024, 1, 4, 8, 0), at(2024, 1, 5, 8, 0),
+        ]);
+    }
+
+    #[test]
+    fn until_date_includes_the_whole_day() {
+        let events = read("until_date.ics", date(2024, 1, 1), date(2024, 2, 1));
+
+        assert_eq!(starts(&events), [at(2024, 1, 1, 20, 0), at(2024, 1, 2, 20, 0), at(2024, 1, 3, 20, 0)]);
+    }
+
+    #[test]
+    fn recurrence_id_replaces_an_occurrence() {
+        let events = read("recurrence_id.ics", date(2024, 1, 1), date(2024, 2, 1));
+
+        assert_eq!(starts(&events), [at(2024, 1, 1, 10, 0), at(2024, 1, 9, 15, 0), at(2024, 1, 15, 10, 0)]);
+        assert_eq!(events[1].summary, "Planning (moved)");
+        assert_eq!(events[1].location.as_deref(), Some("Room 2, upstairs"));
+        assert_eq!(events[2].summary, "Planning");
+    }
+
+    #[test]
+    fn all_day_events_with_and_without_end() {
+        let events = read("all_day.ics", date(2024, 1, 1), date(2024, 2, 1));
+
+        assert!(events.iter().all(|event| event.all_day));
+        assert_eq!(events.iter().map(|event| event.summary.as_str()).collect::<Vec<_>>(), ["Trip", "Holiday"]);
+
+        let (trip, holiday) = (&events[0], &events[1]);
+        assert_eq!((trip.start, trip.end), (local_midnight(date(2024, 1, 5)), local_midnight(date(2024, 1, 7))));
+        assert!(trip.occurs_on(date(2024, 1, 6)) && !trip.occurs_on(date(2024, 1, 7)));
+
+        // Without DTEND, an all-day event lasts its one day.
+        assert_eq!((holiday.start, holiday.end), (local_midnight(date(2024, 1, 10)), local_midnight(date(2024, 1, 11))));
+    }
+
+    #[test]
+    fn zoned_events_across_a_dst_change() {
+        let events = read("dst.ics", date(2024, 3, 20), date(2024, 4, 10));
+        let utc = |event: &AgendaEvent| (event.start.naive_utc(), event.end.naive_utc());
+
+        let weekly = events.iter().filter(|event| event.summary == "Berlin sync").collect::<Vec<_>>();
+        assert_eq!(weekly.iter().map(|event| utc(event)).collect::<Vec<_>>(), [
+            (at(2024, 3, 25, 9, 0), at(2024, 3, 25, 10, 0)),
+            (at(2024, 4, 1, 8, 0), at(2024, 4, 1, 9, 0)),
+        ]);
+
+        // 22:00 CET to 06:00 CEST is only 7 hours.
+        let night = events.iter().find(|event| event.summary == "Night shift").unwrap();
+        assert_eq!(utc(night), (at(2024, 3, 30, 21, 0), at(2024, 3, 31, 4, 0)));
+    }
+
+    #[test]
+    fn events_outside_the_window_are_left_out() {
+        let events = read("weekly_byday.ics", date(2024, 1, 9), date(2024, 1, 11));
+
+        assert_eq!(starts(&events), [at(2024, 1, 10, 9, 0)]);
+    }
+
+    #[test]
+    fn expand_rule() {
+        let rule = RecurrenceRule::parse("FREQ=MONTHLY;BYMONTHDAY=31", TimeKind::Floating).unwrap();
+        let mut occurrences = Vec::new();
+
+        rule.expand(at(2024, 1, 31, 12, 0), |occurrence| {
+            occurrences.push(occurrence);
+            occurrences.len() < 4
+        });
+
+        assert_eq!(occurrences, [at(2024, 1, 31, 12, 0), at(2024, 3, 31, 12, 0), at(2024, 5, 31, 12, 0), at(2024, 7, 31, 12, 0)]);
+    }
+
+    #[test]
+    fn parse_rule() {
+        let rule = RecurrenceRule::parse("FREQ=MONTHLY;INTERVAL=2;BYDAY=-1FR,2MO;WKST=MO", TimeKind::Floating).unwrap();
+
+        assert_eq!(rule.frequency, Frequency::Monthly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(rule.by_day, [(Some(-1), Weekday::Fri), (Some(2), Weekday::Mon)]);
+
+        assert!(RecurrenceRule::parse("INTERVAL=2", TimeKind::Floating).is_err());
+        assert!(RecurrenceRule::parse("FREQ=HOURLY", TimeKind::Floating).is_err());
+        assert!(RecurrenceRule::parse("FREQ=DAILY;BYSETPOS=1", TimeKind::Floating).is_err());
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("PT1H30M"), Some(TimeDelta::minutes(90)));
+        assert_eq!(parse_duration("P1W2D"), Some(TimeDelta::days(9)));
+        assert_eq!(parse_duration("-PT15M"), Some(TimeDelta::minutes(-15)));
+        assert_eq!(parse_duration("1H"), None);
+    }
+}
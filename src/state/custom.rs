@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::watch;
+use tokio::sync::watch::Sender;
+
+use crate::event::Event;
+use super::{ChannelMessage, HorizonState, Provider, Refresh, StateError};
+
+/// User defined states by name, filled from the `[[state]]` tables of the config.
+pub static CUSTOM_STATES: Lazy<RwLock<HashMap<String, Arc<RwLock<CustomState>>>>> = Lazy::new(|| {
+    RwLock::new(HashMap::new())
+});
+
+/// A `[[state]]` table of the user config.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CustomStateDef {
+    pub name: String,
+    pub provider: Provider,
+    /// Re-read the provider every `interval` seconds instead of waiting for it to push data.
+    #[serde(default)]
+    pub interval: Option<u64>,
+}
+
+/// A state holding whatever JSON document its provider delivers.
+#[derive(Debug)]
+pub struct CustomState {
+    pub name: String,
+    pub value: Value,
+    provider: Provider,
+    refresh: Refresh,
+    sender: Sender<ChannelMessage>,
+}
+
+impl CustomState {
+    pub fn new(def: &CustomStateDef) -> Self {
+        let (sender, receiver) = watch::channel(ChannelMessage::Init);
+        let refresh = match def.interval {
+            Some(seconds) => Refresh::Interval(Duration::from_secs(seconds)),
+            None => Refresh::Push,
+        };
+
+        Self {
+            name: def.name.clone(),
+            value: Value::Null,
+            provider: def.provider.clone(),
+            refresh,
+            sender,
+        }
+    }
+
+    /// Creates the state and adds it to [`CUSTOM_STATES`], replacing any state of the same name.
+    pub fn register(def: &CustomStateDef) -> Arc<RwLock<Self>> {
+        let state = Arc::new(RwLock::new(Self::new(def)));
+
+        CUSTOM_STATES.write().unwrap().insert(def.name.clone(), state.clone());
+        state
+    }
+
+    pub fn get(name: &str) -> Option<Arc<RwLock<Self>>> {
+        CUSTOM_STATES.read().unwrap().get(name).cloned()
+    }
+}
+
+impl HorizonState for CustomState {
+    type Data = Value;
+
+    fn provider(&self) -> Provider {
+        self.provider.clone()
+    }
+
+    fn refresh_mode(&self) -> Refresh {
+        self.refresh
+    }
+
+    fn refresh(&mut self) -> Result<(), StateError> {
+        // The value only ever comes from the provider.
+        Ok(())
+    }
+
+    fn apply(&mut self, data: Value) {
+        self.value = data;
+    }
+
+    fn event(&self) -> Event {
+        Event::StateUpdated(self.name.clone())
+    }
+
+    fn sender(&self) -> &Sender<ChannelMessage> {
+        &self.sender
+    }
+}
@@ -1,15 +1,20 @@
-use std::fs::File;
-use std::sync::RwLock;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use chrono::prelude::*;
+use chrono_tz::Tz;
 use once_cell::sync::Lazy;
+use serde::Deserialize;
 use tokio::sync::watch;
 use tokio::sync::watch::Sender;
+use tokio::sync::Notify;
 
-use super::ChannelMessage;
+use crate::event::Event;
+use super::{ChannelMessage, HorizonState, Provider, Refresh, StateError};
 
-pub static DATETIME: Lazy<RwLock<HorizonDateTime>> = Lazy::new(|| {
-    RwLock::new(HorizonDateTime::new_from_chrono())
+pub static DATETIME: Lazy<Arc<RwLock<HorizonDateTime>>> = Lazy::new(|| {
+    Arc::new(RwLock::new(HorizonDateTime::new_from_chrono()))
 });
 
 // Important things
@@ -19,25 +24,52 @@ pub static DATETIME: Lazy<RwLock<HorizonDateTime>> = Lazy::new(|| {
 pub struct HorizonDateTime {
     pub date: HorizonDate,
     pub time: HorizonTime,
+    /// The instant `date` and `time` were taken at, for formatting.
+    pub now: LocalDateTime,
+    pub provider: Provider,
     pub sender: Sender<ChannelMessage>,
+    /// The date and time in every timezone shown by a widget, in the order they were first shown.
+    pub timezones: Vec<HorizonZonedDateTime>,
+    /// Number of widgets showing seconds. Without any, the state only ticks once a minute.
+    seconds_users: usize,
+    /// Number of widgets showing each of `timezones`.
+    timezone_users: Vec<(HorizonTimezone, usize)>,
+    /// Wakes the refresh loop when switching between ticking every second and every minute.
+    refresh_now: Arc<Notify>,
 }
 
+/// The date and time of `now` in one of the timezones of [`HorizonDateTime`].
 #[derive(Debug)]
+pub struct HorizonZonedDateTime {
+    pub timezone: HorizonTimezone,
+    pub date: HorizonDate,
+    pub time: HorizonTime,
+}
+
+/// The document read by data providers, e.g. `{"date": {...}, "time": {...}}`.
+#[derive(Debug, Deserialize)]
+pub struct HorizonDateTimeData {
+    pub date: HorizonDate,
+    pub time: HorizonTime,
+}
+
+#[derive(Debug, Deserialize)]
 pub struct HorizonDate {
     pub year: u32,
     pub month: Month,
     pub day: Day,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
 pub struct HorizonTime {
     pub hour: u32,
     pub minute: u32,
     pub second: u32,
-    // pub timezone: String,
+    /// The abbreviation of the timezone, or its UTC offset if it has none, e.g. `CEST` or `+02:00`.
+    pub timezone: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
 pub struct Month {
     pub name: String,
     pub short_name: String,
@@ -45,7 +77,7 @@ pub struct Month {
     pub month: Months,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
 pub struct Day {
     pub name: String,
     pub short_name: String,
@@ -56,7 +88,7 @@ pub struct Day {
     pub week_number: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
 pub enum Months {
     January,
     February,
@@ -72,7 +104,7 @@ pub enum Months {
     December,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
 pub enum Weekday {
     Monday,
     Tuesday,
@@ -83,12 +115,21 @@ pub enum Weekday {
     Sunday,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize)]
 pub enum DayType {
     Weekday,
     Weekend,
 }
 
+/// A timezone to show dates and times in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HorizonTimezone {
+    /// The timezone of the system.
+    Local,
+    /// An IANA timezone, e.g. `Europe/Berlin`.
+    Named(Tz),
+}
+
 #[derive(Debug)]
 pub enum IntoMonthsError {
     NotAMonthName,
@@ -107,12 +148,81 @@ impl HorizonDateTime {
         Self {
             date: HorizonDate::new_from_chrono(),
             time: HorizonTime::new_from_chrono(),
+            now: LocalDateTime::new(),
+            provider: Provider::Function,
             sender,
+            timezones: Vec::new(),
+            seconds_users: 0,
+            timezone_users: Vec::new(),
+            refresh_now: Arc::new(Notify::new()),
         }
     }
 
     pub fn update(&mut self) {
+        self.now.update();
+        self.update_date_from_chrono();
         self.update_time_from_chrono();
+        self.update_timezones();
+    }
+
+    /// Keeps the date and time in `timezones` up to date until the matching [`HorizonDateTime::release_timezones`].
+    pub fn require_timezones(&mut self, timezones: &[HorizonTimezone]) {
+        for timezone in timezones {
+            match self.timezone_users.iter_mut().find(|(known, _)| known == timezone) {
+                Some((_, users)) => *users += 1,
+                None => self.timezone_users.push((*timezone, 1)),
+            }
+        }
+
+        self.update_timezones();
+    }
+
+    pub fn release_timezones(&mut self, timezones: &[HorizonTimezone]) {
+        for timezone in timezones {
+            if let Some((_, users)) = self.timezone_users.iter_mut().find(|(known, _)| known == timezone) {
+                *users = users.saturating_sub(1);
+            }
+        }
+
+        self.timezone_users.retain(|(_, users)| *users > 0);
+        self.update_timezones();
+    }
+
+    /// The date and time in `timezone`, if a widget requires it.
+    pub fn in_timezone(&self, timezone: &HorizonTimezone) -> Option<&HorizonZonedDateTime> {
+        self.timezones.iter().find(|zoned| zoned.timezone == *timezone)
+    }
+
+    fn update_timezones(&mut self) {
+        self.timezones = self.timezone_users
+            .iter()
+            .map(|(timezone, _)| HorizonZonedDateTime::new(*timezone, &self.now.0))
+            .collect();
+    }
+
+    /// Makes the state tick every second until the matching [`HorizonDateTime::release_seconds`].
+    pub fn require_seconds(&mut self) {
+        self.seconds_users += 1;
+
+        if self.seconds_users == 1 {
+            self.refresh_now.notify_one();
+        }
+    }
+
+    pub fn release_seconds(&mut self) {
+        if self.seconds_users == 1 {
+            self.refresh_now.notify_one();
+        }
+
+        self.seconds_users = self.seconds_users.saturating_sub(1);
+    }
+
+    /// Formats the current instant in `timezone` with a strftime `format`.
+    ///
+    /// The offset, and so daylight saving time, is looked up for the instant itself,
+    /// and `%Z` gives the abbreviation in effect, e.g. `CEST` in summer and `CET` in winter.
+    pub fn format_in(&self, timezone: &HorizonTimezone, format: &str) -> String {
+        timezone.format(&self.now.0, format)
     }
 
     pub fn update_date_from_chrono(&mut self) {
@@ -124,20 +234,66 @@ impl HorizonDateTime {
     }
 }
 
+impl HorizonState for HorizonDateTime {
+    type Data = HorizonDateTimeData;
+
+    fn provider(&self) -> Provider {
+        self.provider.clone()
+    }
+
+    fn refresh_mode(&self) -> Refresh {
+        match self.seconds_users {
+            0 => Refresh::Aligned(Duration::from_secs(60)),
+            _ => Refresh::Aligned(Duration::from_secs(1)),
+        }
+    }
+
+    fn refresh(&mut self) -> Result<(), StateError> {
+        self.update();
+        Ok(())
+    }
+
+    fn refresh_now(&self) -> Option<Arc<Notify>> {
+        Some(self.refresh_now.clone())
+    }
+
+    fn apply(&mut self, data: HorizonDateTimeData) {
+        self.now.update();
+        self.date = data.date;
+        self.time = data.time;
+        self.update_timezones();
+    }
+
+    fn event(&self) -> Event {
+        Event::HorizonDateTimeUpdated
+    }
+
+    fn sender(&self) -> &Sender<ChannelMessage> {
+        &self.sender
+    }
+}
+
 impl HorizonDate {
     pub fn new_from_chrono() -> Self {
-        let now = Local::now();
+        Self::from_datetime(&Local::now())
+    }
 
-        let year = now.year() as u32;
+    /// The date of `now` in its timezone.
+    pub fn from_datetime<Tz: TimeZone>(now: &DateTime<Tz>) -> Self {
+        Self::from_date(&now.date_naive())
+    }
+
+    pub fn from_date(date: &NaiveDate) -> Self {
+        let year = date.year() as u32;
         let month = Month::new(
-            Months::try_from(now.month()).unwrap().to_string().as_str(),
-            now.month(),
+            Months::try_from(date.month()).unwrap().to_string().as_str(),
+            date.month(),
         );
         let day = Day::new(
-            now.weekday().to_string().as_str(),
-            now.ordinal(),
-            now.day(),
-            now.iso_week().week(),
+            date.weekday().to_string().as_str(),
+            date.ordinal(),
+            date.day(),
+            date.iso_week().week(),
         );
 
         Self {
@@ -150,32 +306,62 @@ impl HorizonDate {
 
 impl HorizonTime {
     pub fn new_from_chrono() -> Self {
-        let now = Local::now();
-        let tz = now.timezone();
+        Self::from_datetime(&Local::now())
+    }
 
+    /// The time of `now` in its timezone.
+    pub fn from_datetime<Tz: TimeZone>(now: &DateTime<Tz>) -> Self
+    where
+        Tz::Offset: std::fmt::Display,
+    {
         Self {
             hour: now.hour(),
             minute: now.minute(),
             second: now.second(),
+            timezone: now.format("%Z").to_string(),
         }
     }
 }
 
-impl From<File> for HorizonDateTime {
-    fn from(json_file: File) -> Self {
-        todo!()
+impl HorizonZonedDateTime {
+    pub fn new<T: TimeZone>(timezone: HorizonTimezone, now: &DateTime<T>) -> Self {
+        match timezone {
+            HorizonTimezone::Local => Self::from_datetime(timezone, &now.with_timezone(&Local)),
+            HorizonTimezone::Named(tz) => Self::from_datetime(timezone, &now.with_timezone(&tz)),
+        }
+    }
+
+    fn from_datetime<T: TimeZone>(timezone: HorizonTimezone, now: &DateTime<T>) -> Self
+    where
+        T::Offset: std::fmt::Display,
+    {
+        Self {
+            timezone,
+            date: HorizonDate::from_datetime(now),
+            time: HorizonTime::from_datetime(now),
+        }
     }
 }
 
-impl From<File> for HorizonDate {
-    fn from(json_file: File) -> Self {
-        todo!()
+impl HorizonTimezone {
+    /// Formats `instant` in this timezone with a strftime `format`.
+    pub fn format<T: TimeZone>(&self, instant: &DateTime<T>, format: &str) -> String {
+        match self {
+            HorizonTimezone::Local => instant.with_timezone(&Local).format(format).to_string(),
+            HorizonTimezone::Named(tz) => instant.with_timezone(tz).format(format).to_string(),
+        }
     }
 }
 
-impl From<File> for HorizonTime {
-    fn from(json_file: File) -> Self {
-        todo!()
+impl FromStr for HorizonTimezone {
+    type Err = String;
+
+    /// Parses `local` or an IANA timezone name.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "local" => Ok(HorizonTimezone::Local),
+            name => name.parse::<Tz>().map(HorizonTimezone::Named).map_err(|_| format!("Unknown timezone '{name}'")),
+        }
     }
 }
 
@@ -314,3 +500,83 @@ impl LocalDateTime {
         self.0 = chrono::Local::now();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        timestamp.parse().unwrap()
+    }
+
+    fn named(name: &str) -> HorizonTimezone {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_local_and_iana_names() {
+        assert_eq!(named("local"), HorizonTimezone::Local);
+        assert_eq!(named("Europe/Berlin"), HorizonTimezone::Named(chrono_tz::Europe::Berlin));
+        assert!("Europe/Atlantis".parse::<HorizonTimezone>().is_err());
+    }
+
+    #[test]
+    fn berlin_springs_forward() {
+        let berlin = named("Europe/Berlin");
+
+        assert_eq!(berlin.format(&at("2024-03-31T00:59:59Z"), "%H:%M:%S %Z"), "01:59:59 CET");
+        assert_eq!(berlin.format(&at("2024-03-31T01:00:00Z"), "%H:%M:%S %Z"), "03:00:00 CEST");
+    }
+
+    #[test]
+    fn berlin_falls_back() {
+        let berlin = named("Europe/Berlin");
+
+        assert_eq!(berlin.format(&at("2024-10-27T00:59:59Z"), "%H:%M:%S %Z"), "02:59:59 CEST");
+        assert_eq!(berlin.format(&at("2024-10-27T01:00:00Z"), "%H:%M:%S %Z"), "02:00:00 CET");
+    }
+
+    #[test]
+    fn new_york_springs_forward_and_falls_back() {
+        let new_york = named("America/New_York");
+
+        assert_eq!(new_york.format(&at("2024-03-10T06:59:59Z"), "%H:%M %Z"), "01:59 EST");
+        assert_eq!(new_york.format(&at("2024-03-10T07:00:00Z"), "%H:%M %Z"), "03:00 EDT");
+        assert_eq!(new_york.format(&at("2024-11-03T05:59:59Z"), "%H:%M %Z"), "01:59 EDT");
+        assert_eq!(new_york.format(&at("2024-11-03T06:00:00Z"), "%H:%M %Z"), "01:00 EST");
+    }
+
+    #[test]
+    fn zoned_date_follows_the_timezone() {
+        // Still Sunday evening in New York, already Monday in Berlin.
+        let instant = at("2024-03-31T23:30:00Z");
+
+        let berlin = HorizonZonedDateTime::new(named("Europe/Berlin"), &instant);
+        assert_eq!((berlin.date.day.day_of_month, berlin.time.hour, berlin.time.minute), (1, 1, 30));
+        assert_eq!(berlin.date.month.number, 4);
+        assert_eq!(berlin.time.timezone, "CEST");
+
+        let new_york = HorizonZonedDateTime::new(named("America/New_York"), &instant);
+        assert_eq!((new_york.date.day.day_of_month, new_york.time.hour), (31, 19));
+        assert!(matches!(new_york.date.day.weekday, Weekday::Sunday));
+        assert_eq!(new_york.time.timezone, "EDT");
+    }
+
+    #[test]
+    fn timezones_are_tracked_while_required() {
+        let berlin = named("Europe/Berlin");
+        let tokyo = named("Asia/Tokyo");
+        let mut datetime = HorizonDateTime::new_from_chrono();
+
+        datetime.require_timezones(&[berlin, tokyo]);
+        datetime.require_timezones(&[berlin]);
+        assert_eq!(datetime.timezones.iter().map(|zoned| zoned.timezone).collect::<Vec<_>>(), [berlin, tokyo]);
+
+        datetime.release_timezones(&[berlin, tokyo]);
+        assert!(datetime.in_timezone(&berlin).is_some());
+        assert!(datetime.in_timezone(&tokyo).is_none());
+
+        datetime.release_timezones(&[berlin]);
+        assert!(datetime.timezones.is_empty());
+    }
+}
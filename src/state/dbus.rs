@@ -0,0 +1,195 @@
+use std::sync::{Arc, RwLock};
+
+use futures_util::StreamExt;
+use serde::Deserialize;
+use serde_json::{Map, Number};
+use tokio::time::{self, Instant};
+use tracing::{debug, warn};
+use zbus::fdo::PropertiesProxy;
+use zbus::names::InterfaceName;
+use zbus::zvariant::{Optional, Value};
+use zbus::{Connection, ConnectionBuilder};
+
+use super::external::{MAX_BACKOFF, MIN_BACKOFF};
+use super::provider::apply_data;
+use super::{HorizonState, StateError};
+
+/// The properties of a single interface of a D-Bus object.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DbusProperties {
+    #[serde(default)]
+    pub bus: DbusBus,
+    /// The bus name of the service, e.g. `org.freedesktop.UPower`.
+    pub destination: String,
+    /// The object path, e.g. `/org/freedesktop/UPower/devices/DisplayDevice`.
+    pub path: String,
+    /// The interface whose properties are mapped onto the state, e.g. `org.freedesktop.UPower.Device`.
+    pub interface: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DbusBus {
+    #[default]
+    Session,
+    System,
+    /// Any bus by address, e.g. `unix:path=/tmp/test-bus` for a private `dbus-daemon`.
+    Address(String),
+}
+
+/// Mirrors the properties of the interface into the state as a JSON object,
+/// updating it on every `PropertiesChanged` signal.
+///
+/// Reconnects with an increasing delay whenever the connection fails or the bus goes away,
+/// and re-reads all properties whenever the service is (re)started.
+pub(super) async fn run_dbus<S: HorizonState>(state: Arc<RwLock<S>>, source: DbusProperties) -> Result<(), StateError> {
+    let interface = InterfaceName::try_from(source.interface.as_str())
+        .map_err(|err| StateError::Dbus { interface: source.interface.clone(), source: err.into() })?;
+
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        let started = Instant::now();
+
+        let err = match watch_properties(&state, &source, &interface).await {
+            Ok(()) => StateError::DbusClosed { interface: source.interface.clone() },
+            Err(err) => err,
+        };
+
+        if started.elapsed() >= MAX_BACKOFF {
+            backoff = MIN_BACKOFF;
+        }
+
+        warn!("{err}, reconnecting in {}s", backoff.as_secs());
+        state.read().unwrap().fail(&err);
+
+        time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+/// Reads all properties, then follows their changes until the connection ends.
+async fn watch_properties<S: HorizonState>(state: &RwLock<S>, source: &DbusProperties, interface: &InterfaceName<'_>) -> Result<(), StateError> {
+    let dbus_error = |err: zbus::Error| StateError::Dbus { interface: source.interface.clone(), source: err };
+
+    let connection = connect(&source.bus).await.map_err(dbus_error)?;
+
+    let proxy = PropertiesProxy::builder(&connection)
+        .destination(source.destination.as_str())
+        .and_then(|builder| builder.path(source.path.as_str()))
+        .map_err(dbus_error)?
+        .build()
+        .await
+        .map_err(dbus_error)?;
+
+    // Subscribe before reading the initial values so no change falls in between.
+    let mut changes = proxy.receive_properties_changed().await.map_err(dbus_error)?;
+    let mut owners = proxy.inner().receive_owner_changed().await.map_err(dbus_error)?;
+
+    let mut properties = get_all(&proxy, interface).await.map_err(dbus_error)?;
+    apply_properties(state, &source.interface, &properties);
+
+    loop {
+        tokio::select! {
+            signal = changes.next() => {
+                let Some(signal) = signal else {
+                    return Ok(());
+                };
+
+                let args = signal.args().map_err(dbus_error)?;
+
+                if args.interface_name() != interface {
+                    continue;
+                }
+
+                for (name, value) in args.changed_properties() {
+                    properties.insert(name.to_string(), to_json(value));
+                }
+
+                // Invalidated properties announce a change without the new value.
+                for name in args.invalidated_properties() {
+                    match proxy.get(interface.clone(), name).await {
+                        Ok(value) => properties.insert(name.to_string(), to_json(&value)),
+                        Err(err) => {
+                            debug!("Failed to get invalidated property {name}: {err}");
+                            properties.remove(*name)
+                        },
+                    };
+                }
+            },
+            owner = owners.next() => match owner {
+                // A restarted service does not announce the values it starts with.
+                Some(Some(_)) => properties = get_all(&proxy, interface).await.map_err(dbus_error)?,
+                Some(None) => {
+                    debug!("{} left the bus, waiting for it to come back", source.destination);
+                    continue;
+                },
+                None => return Ok(()),
+            },
+        }
+
+        apply_properties(state, &source.interface, &properties);
+    }
+}
+
+async fn get_all(proxy: &PropertiesProxy<'_>, interface: &InterfaceName<'_>) -> zbus::Result<Map<String, serde_json::Value>> {
+    let properties = proxy.get_all(Optional::from(Some(interface.clone()))).await?;
+
+    Ok(properties
+        .iter()
+        .map(|(name, value)| (name.clone(), to_json(value)))
+        .collect())
+}
+
+async fn connect(bus: &DbusBus) -> zbus::Result<Connection> {
+    match bus {
+        DbusBus::Session => Connection::session().await,
+        DbusBus::System => Connection::system().await,
+        DbusBus::Address(address) => ConnectionBuilder::address(address.as_str())?.build().await,
+    }
+}
+
+fn apply_properties<S: HorizonState>(state: &RwLock<S>, interface: &str, properties: &Map<String, serde_json::Value>) {
+    let data = serde_json::from_value(serde_json::Value::Object(properties.clone()))
+        .map_err(|source| StateError::Properties { interface: interface.to_owned(), source });
+
+    apply_data(state, data);
+}
+
+/// Converts a D-Bus value into the closest JSON value. Structures become arrays.
+fn to_json(value: &Value<'_>) -> serde_json::Value {
+    use serde_json::Value as Json;
+
+    match value {
+        Value::U8(number) => Json::from(*number),
+        Value::Bool(boolean) => Json::from(*boolean),
+        Value::I16(number) => Json::from(*number),
+        Value::U16(number) => Json::from(*number),
+        Value::I32(number) => Json::from(*number),
+        Value::U32(number) => Json::from(*number),
+        Value::I64(number) => Json::from(*number),
+        Value::U64(number) => Json::from(*number),
+        Value::F64(number) => Number::from_f64(*number).map(Json::Number).unwrap_or(Json::Null),
+        Value::Str(string) => Json::from(string.as_str()),
+        Value::Signature(signature) => Json::from(signature.as_str()),
+        Value::ObjectPath(path) => Json::from(path.as_str()),
+        Value::Value(value) => to_json(value),
+        Value::Array(array) => array.iter().map(to_json).collect(),
+        Value::Dict(dict) => dict
+            .iter()
+            .map(|(key, value)| (key_to_string(key), to_json(value)))
+            .collect::<Map<_, _>>()
+            .into(),
+        Value::Structure(structure) => structure.fields().iter().map(to_json).collect(),
+        #[cfg(unix)]
+        Value::Fd(_) => Json::Null,
+    }
+}
+
+fn key_to_string(key: &Value<'_>) -> String {
+    match to_json(key) {
+        serde_json::Value::String(string) => string,
+        key => key.to_string(),
+    }
+}
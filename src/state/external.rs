@@ -0,0 +1,205 @@
+use std::process::{ExitStatus, Stdio};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use nix::sys::signal::{killpg, Signal};
+use nix::unistd::Pid;
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::task;
+use tokio::time::{self, Instant, MissedTickBehavior};
+use tracing::warn;
+
+use super::provider::apply_data;
+use super::{HorizonState, Provider, Refresh, StateError};
+
+/// Delay before restarting a tailed command that exited, or reconnecting to D-Bus.
+pub(super) const MIN_BACKOFF: Duration = Duration::from_secs(1);
+/// Upper bound for the restart delay. A command that ran at least this long starts over at [`MIN_BACKOFF`].
+pub(super) const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// A shell command feeding a state with its output.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExternalCommand {
+    /// Run through `sh -c`.
+    pub command: String,
+    /// Keep the command running and use every line it prints, instead of running it once per interval.
+    #[serde(default)]
+    pub tail: bool,
+    #[serde(default)]
+    pub format: OutputFormat,
+    /// Seconds an interval command may run, or a tailed command may stay silent, before it is killed.
+    /// Interval commands default to the interval itself.
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+/// How the output of an [`ExternalCommand`] is mapped onto the state.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// The output is used as a JSON string.
+    #[default]
+    Text,
+    /// The output is parsed as a JSON document.
+    Json,
+}
+
+pub(super) async fn run_external<S: HorizonState>(state: Arc<RwLock<S>>, external: ExternalCommand) -> Result<(), StateError> {
+    let refresh = state.read().unwrap().refresh_mode();
+
+    match (external.tail, refresh) {
+        (true, _) => run_tail(state, external).await,
+        (false, Refresh::Interval(period)) => run_interval(state, external, period).await,
+        (false, refresh) => Err(StateError::InvalidRefresh(Provider::External(external), refresh)),
+    }
+}
+
+/// Runs the command on every tick, using its whole output.
+async fn run_interval<S: HorizonState>(state: Arc<RwLock<S>>, external: ExternalCommand, period: Duration) -> Result<(), StateError> {
+    let timeout = external.timeout.map(Duration::from_secs).unwrap_or(period);
+    let mut interval = time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        let data = run_once(&external, timeout)
+            .await
+            .and_then(|output| parse_output::<S::Data>(&external, &output));
+
+        apply_data(&state, data);
+    }
+}
+
+async fn run_once(external: &ExternalCommand, timeout: Duration) -> Result<String, StateError> {
+    let (child, _group) = spawn(&external.command)?;
+
+    let output = time::timeout(timeout, child.wait_with_output())
+        .await
+        .map_err(|_| StateError::Timeout { command: external.command.clone(), timeout })?
+        .map_err(|source| StateError::Spawn { command: external.command.clone(), source })?;
+
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        warn!(command = %external.command, "{line}");
+    }
+
+    if !output.status.success() {
+        return Err(StateError::Exited { command: external.command.clone(), status: output.status });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim_end().to_owned())
+}
+
+/// Keeps the command running, restarting it with an increasing delay whenever it exits.
+async fn run_tail<S: HorizonState>(state: Arc<RwLock<S>>, external: ExternalCommand) -> Result<(), StateError> {
+    let mut backoff = MIN_BACKOFF;
+
+    loop {
+        let started = Instant::now();
+
+        let err = match tail_once(&state, &external).await {
+            Ok(status) => StateError::Exited { command: external.command.clone(), status },
+            Err(err) => err,
+        };
+
+        if started.elapsed() >= MAX_BACKOFF {
+            backoff = MIN_BACKOFF;
+        }
+
+        warn!("{err}, restarting in {}s", backoff.as_secs());
+        state.read().unwrap().fail(&err);
+
+        time::sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+async fn tail_once<S: HorizonState>(state: &RwLock<S>, external: &ExternalCommand) -> Result<ExitStatus, StateError> {
+    let spawn_error = |source| StateError::Spawn { command: external.command.clone(), source };
+
+    let (mut child, _group) = spawn(&external.command)?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let stderr = child.stderr.take().expect("stderr is piped");
+
+    task::spawn(log_stderr(external.command.clone(), stderr));
+
+    let mut lines = BufReader::new(stdout).lines();
+
+    loop {
+        let line = match external.timeout {
+            Some(timeout) => {
+                let timeout = Duration::from_secs(timeout);
+                time::timeout(timeout, lines.next_line())
+                    .await
+                    .map_err(|_| StateError::Timeout { command: external.command.clone(), timeout })?
+            },
+            None => lines.next_line().await,
+        };
+
+        match line.map_err(spawn_error)? {
+            Some(line) => apply_data(state, parse_output::<S::Data>(external, &line)),
+            None => break,
+        }
+    }
+
+    child.wait().await.map_err(spawn_error)
+}
+
+async fn log_stderr(command: String, stderr: impl AsyncRead + Unpin) {
+    let mut lines = BufReader::new(stderr).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        warn!(command = %command, "{line}");
+    }
+}
+
+fn parse_output<T: DeserializeOwned>(external: &ExternalCommand, output: &str) -> Result<T, StateError> {
+    let parsed = match external.format {
+        OutputFormat::Text => serde_json::from_value(Value::String(output.to_owned())),
+        OutputFormat::Json => serde_json::from_str(output),
+    };
+
+    parsed.map_err(|source| StateError::Output { command: external.command.clone(), source })
+}
+
+/// Kills a process group when dropped, i.e. when its command times out, is restarted or its state stops.
+///
+/// Killing only `sh` would leave the rest of a pipeline like `cmd | jq` running.
+struct ProcessGroup(Pid);
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        // Fails with ESRCH once every process of the group has exited.
+        let _ = killpg(self.0, Signal::SIGKILL);
+    }
+}
+
+/// Starts `command` in its own process group, which is killed along with the returned [`ProcessGroup`].
+fn spawn(command: &str) -> Result<(Child, ProcessGroup), StateError> {
+    let child = shell(command)
+        .spawn()
+        .map_err(|source| StateError::Spawn { command: command.to_owned(), source })?;
+
+    // A spawned child has an id until it is waited on.
+    let group = ProcessGroup(Pid::from_raw(child.id().expect("child was not waited on") as i32));
+    Ok((child, group))
+}
+
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+
+    shell.arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true);
+
+    shell
+}
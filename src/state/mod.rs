@@ -1,29 +1,185 @@
 #![allow(unused_imports)]
 
+mod agenda;
+mod custom;
 mod datetime;
+mod dbus;
+mod external;
+mod provider;
 mod wm;
 
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+use tokio::sync::watch::Sender;
+use tokio::sync::Notify;
+
+use crate::event::{EVENT_MANAGER, Event};
+
 pub use crate::state::{
+    agenda::AGENDA,
+    agenda::AgendaEvent,
+    agenda::HorizonAgenda,
+    agenda::local_midnight,
+    custom::CUSTOM_STATES,
+    custom::CustomState,
+    custom::CustomStateDef,
     datetime::DATETIME,
+    datetime::DayType,
+    datetime::HorizonDate,
     datetime::HorizonDateTime,
+    datetime::HorizonTimezone,
+    datetime::HorizonZonedDateTime,
+    dbus::DbusBus,
+    dbus::DbusProperties,
+    external::ExternalCommand,
+    external::OutputFormat,
+    provider::spawn_state,
     wm::WM,
     wm::HorizonWm,
+    wm::HorizonWmWindow,
+    wm::HorizonWmWorkspace,
 };
 
-pub trait HorizonState {
-    
+/// A piece of data shown by widgets, kept up to date by a [`Provider`].
+///
+/// States are shared as `Arc<RwLock<_>>` and driven by [`spawn_state`] on the tokio runtime.
+pub trait HorizonState: Send + Sync + 'static {
+    /// The document delivered by data providers such as [`Provider::JsonFile`].
+    type Data: DeserializeOwned + Send + 'static;
+
+    /// Where the data of this state comes from.
+    fn provider(&self) -> Provider;
+
+    /// Whether the state is polled or pushed by its provider.
+    fn refresh_mode(&self) -> Refresh;
+
+    /// Pulls fresh data into the state. Called on every tick of a polled [`Provider::Function`] state.
+    fn refresh(&mut self) -> Result<(), StateError>;
+
+    /// Reads fresh data for a polled [`Provider::Function`] state without holding its lock, for refreshes
+    /// doing slow I/O. The data is applied with [`HorizonState::apply`], instead of calling `refresh`.
+    fn loader(&self) -> Option<Loader<Self::Data>> {
+        None
+    }
+
+    /// Notified when a polled state should refresh right away instead of on its next tick,
+    /// e.g. when its [`HorizonState::refresh_mode`] or its inputs change.
+    /// A [`Refresh::Aligned`] state then starts over with the new period instead of finishing the old one.
+    fn refresh_now(&self) -> Option<Arc<Notify>> {
+        None
+    }
+
+    /// Replaces the state with a document delivered by its provider.
+    fn apply(&mut self, data: Self::Data);
+
+    /// The event announcing an update of this state on the [`EVENT_MANAGER`] bus.
+    fn event(&self) -> Event;
+
+    fn sender(&self) -> &Sender<ChannelMessage>;
+
+    /// Tells both watch channel subscribers and bus listeners that the state changed.
+    fn publish(&self) {
+        let _ = self.sender().send(ChannelMessage::Updated);
+        EVENT_MANAGER.notify_listeners(self.event());
+    }
+
+    /// Tells watch channel subscribers that the provider failed to deliver data.
+    fn fail(&self, err: &StateError) {
+        let _ = self.sender().send(ChannelMessage::Error(err.to_string()));
+    }
 }
 
+/// Reads the data of a state on a blocking thread, see [`HorizonState::loader`].
+pub type Loader<T> = Box<dyn FnOnce() -> Result<T, StateError> + Send>;
+
 #[derive(Debug)]
 pub enum ChannelMessage {
     Init,
     Updated,
+    Error(String),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Provider {
     Function,
     JsonFile(String),
-    External(String),
-    Dbus(String),
+    External(ExternalCommand),
+    Dbus(DbusProperties),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Refresh {
+    /// Refresh the state every period.
+    Interval(Duration),
+    /// Refresh the state every period, on the wall clock boundaries of the period (e.g. every full minute).
+    Aligned(Duration),
+    /// The provider pushes new data whenever it is available.
+    Push,
+}
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("{0:?} provider can not be used with {1:?} refresh")]
+    InvalidRefresh(Provider, Refresh),
+    #[error("Failed to read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    #[error("Failed to parse {}: {source}", path.display())]
+    Json {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("Failed to parse calendar {}: {source}", path.display())]
+    Ical {
+        path: PathBuf,
+        source: ical::parser::ParserError,
+    },
+    #[error("Failed to watch {}: {source}", path.display())]
+    Watch {
+        path: PathBuf,
+        source: notify::Error,
+    },
+    #[error("Failed to run `{command}`: {source}")]
+    Spawn {
+        command: String,
+        source: std::io::Error,
+    },
+    #[error("`{command}` did not finish within {}s", timeout.as_secs())]
+    Timeout {
+        command: String,
+        timeout: Duration,
+    },
+    #[error("`{command}` exited with {status}")]
+    Exited {
+        command: String,
+        status: ExitStatus,
+    },
+    #[error("Failed to parse the output of `{command}`: {source}")]
+    Output {
+        command: String,
+        source: serde_json::Error,
+    },
+    #[error("Failed to map the properties of {interface}: {source}")]
+    Properties {
+        interface: String,
+        source: serde_json::Error,
+    },
+    #[error("D-Bus error for {interface}: {source}")]
+    Dbus {
+        interface: String,
+        source: zbus::Error,
+    },
+    #[error("D-Bus connection for {interface} closed")]
+    DbusClosed {
+        interface: String,
+    },
 }
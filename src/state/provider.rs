@@ -0,0 +1,197 @@
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc::{self, UnboundedSender};
+use tokio::sync::Notify;
+use tokio::task::{self, JoinHandle};
+use tokio::time::{self, MissedTickBehavior};
+use tracing::{error, warn};
+
+use super::dbus::run_dbus;
+use super::external::run_external;
+use super::{HorizonState, Provider, Refresh, StateError};
+
+/// Drives `state` from its provider until the runtime shuts down.
+///
+/// Must be called from within the tokio runtime.
+pub fn spawn_state<S: HorizonState>(state: Arc<RwLock<S>>) -> JoinHandle<()> {
+    task::spawn(async move {
+        let provider = state.read().unwrap().provider();
+
+        let result = match provider {
+            Provider::Function => run_function(state).await,
+            Provider::JsonFile(path) => run_json_file(state, PathBuf::from(path)).await,
+            Provider::External(external) => run_external(state, external).await,
+            Provider::Dbus(source) => run_dbus(state, source).await,
+        };
+
+        if let Err(err) = result {
+            error!("State stopped: {err}");
+        }
+    })
+}
+
+/// Polls [`HorizonState::refresh`] at the interval requested by the state.
+async fn run_function<S: HorizonState>(state: Arc<RwLock<S>>) -> Result<(), StateError> {
+    let refresh = state.read().unwrap().refresh_mode();
+    let refresh_now = state.read().unwrap().refresh_now();
+
+    match refresh {
+        Refresh::Interval(period) => {
+            let mut interval = time::interval(period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                tick_or_notified(interval.tick(), refresh_now.as_deref()).await;
+                refresh_function(&state).await;
+            }
+        },
+        Refresh::Aligned(_) => loop {
+            // Read on every tick, aligned states may change their period while running.
+            let period = match state.read().unwrap().refresh_mode() {
+                Refresh::Aligned(period) => period,
+                refresh => return Err(StateError::InvalidRefresh(Provider::Function, refresh)),
+            };
+
+            tick_or_notified(time::sleep(until_boundary(period)), refresh_now.as_deref()).await;
+            refresh_function(&state).await;
+        },
+        refresh => Err(StateError::InvalidRefresh(Provider::Function, refresh)),
+    }
+}
+
+/// Waits for `tick`, or until the state asks to be refreshed right away.
+async fn tick_or_notified(tick: impl Future, refresh_now: Option<&Notify>) {
+    match refresh_now {
+        Some(refresh_now) => tokio::select! {
+            _ = tick => {},
+            _ = refresh_now.notified() => {},
+        },
+        None => {
+            tick.await;
+        },
+    }
+}
+
+async fn refresh_function<S: HorizonState>(state: &RwLock<S>) {
+    let loader = state.read().unwrap().loader();
+
+    let result = match loader {
+        // The lock is only taken to swap in the data, so widgets can keep reading the old data meanwhile.
+        Some(load) => match task::spawn_blocking(load).await {
+            Ok(data) => data.map(|data| state.write().unwrap().apply(data)),
+            Err(err) => {
+                error!("Failed to refresh state: {err}");
+                return;
+            },
+        },
+        None => state.write().unwrap().refresh(),
+    };
+
+    match result {
+        Ok(()) => state.read().unwrap().publish(),
+        Err(err) => error!("Failed to refresh state: {err}"),
+    }
+}
+
+/// Time left until the next multiple of `period` since the unix epoch.
+fn until_boundary(period: Duration) -> Duration {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let period = period.as_nanos().max(1);
+    let remaining = period - since_epoch.as_nanos() % period;
+
+    Duration::from_nanos(remaining as u64)
+}
+
+/// Loads the state from a JSON document, re-reading it whenever the file changes
+/// or, for polled states, on every tick.
+async fn run_json_file<S: HorizonState>(state: Arc<RwLock<S>>, path: PathBuf) -> Result<(), StateError> {
+    let refresh = state.read().unwrap().refresh_mode();
+
+    load_json_file(&state, &path);
+
+    match refresh {
+        Refresh::Interval(period) | Refresh::Aligned(period) => {
+            let mut interval = time::interval(period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            interval.tick().await;
+
+            loop {
+                interval.tick().await;
+                load_json_file(&state, &path);
+            }
+        },
+        Refresh::Push => {
+            let (sender, mut receiver) = mpsc::unbounded_channel();
+            let _watcher = watch_file(&path, sender)?;
+
+            while receiver.recv().await.is_some() {
+                // A single write usually produces several events.
+                while receiver.try_recv().is_ok() {}
+                load_json_file(&state, &path);
+            }
+
+            Ok(())
+        },
+    }
+}
+
+fn load_json_file<S: HorizonState>(state: &RwLock<S>, path: &Path) {
+    apply_data(state, read_json_file::<S::Data>(path));
+}
+
+/// Applies and publishes data delivered by a provider, or reports why there is none.
+pub(super) fn apply_data<S: HorizonState>(state: &RwLock<S>, data: Result<S::Data, StateError>) {
+    match data {
+        Ok(data) => {
+            state.write().unwrap().apply(data);
+            state.read().unwrap().publish();
+        },
+        Err(err) => {
+            warn!("{err}");
+            state.read().unwrap().fail(&err);
+        },
+    }
+}
+
+/// Reads a JSON document from `path` into any deserializable type.
+pub fn read_json_file<T: DeserializeOwned>(path: &Path) -> Result<T, StateError> {
+    let file = File::open(path)
+        .map_err(|source| StateError::Io { path: path.to_owned(), source })?;
+
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|source| StateError::Json { path: path.to_owned(), source })
+}
+
+/// Sends a message to `sender` whenever the file at `path` is created or modified.
+///
+/// The parent directory is watched so files replaced by a rename are still picked up.
+fn watch_file(path: &Path, sender: UnboundedSender<()>) -> Result<RecommendedWatcher, StateError> {
+    let watch_error = |source| StateError::Watch { path: path.to_owned(), source };
+    let file_name = path.file_name().map(ToOwned::to_owned);
+    let directory = path.parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+
+        let is_change = event.kind.is_create() || event.kind.is_modify();
+        let is_our_file = event.paths.iter().any(|path| path.file_name() == file_name.as_deref());
+
+        if is_change && is_our_file {
+            let _ = sender.send(());
+        }
+    }).map_err(watch_error)?;
+
+    watcher.watch(directory, RecursiveMode::NonRecursive).map_err(watch_error)?;
+    Ok(watcher)
+}
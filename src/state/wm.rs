@@ -1,8 +1,16 @@
-use std::fs::File;
-use std::sync::RwLock;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, RwLock};
 
 use once_cell::sync::Lazy;
+use tracing::debug;
+use x11rb::connection::Connection;
+use x11rb::errors::ReplyError;
+use x11rb::protocol::xproto::{Atom, AtomEnum, Window};
 
+use crate::x::ewmh::{self, AtomCollection, WindowType, WmIcon, ALL_DESKTOPS, WM_HINTS_URGENCY};
+use crate::x::strut::StrutPartialDef;
+
+/// Empty until it is read from the X server with [`HorizonWm::from_ewmh`].
 pub static WM: Lazy<RwLock<HorizonWm>> = Lazy::new(|| {
     RwLock::new(HorizonWm::new())
 });
@@ -11,6 +19,10 @@ pub static WM: Lazy<RwLock<HorizonWm>> = Lazy::new(|| {
 pub struct HorizonWm {
     pub workspaces: Vec<HorizonWmWorkspace>,
     pub scratchpads: Vec<HorizonWmScratchpad>,
+    /// The X window ids of every client on any workspace, kept along with the workspaces.
+    window_ids: HashSet<Window>,
+    /// Clients added since the last [`HorizonWm::take_new_windows`].
+    new_windows: Vec<Window>,
 }
 
 #[derive(Debug)]
@@ -20,17 +32,24 @@ pub struct HorizonWmWorkspace {
     pub icon: String,
     pub visible: bool,
     pub screen: Option<u32>,    // INFO: If this workspace is on a screen, the number is the screen index.
+    pub viewport: Option<(i32, i32)>,   // INFO: Top left corner from _NET_DESKTOP_VIEWPORT, if the WM sets it.
     pub layout: String,         // NOTE: Might not need this if the active layout is tracked in layouts.
     pub is_temp: bool,
     pub windows: Vec<HorizonWmWindow>,
     pub layouts: Vec<HorizonWmLayout>
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HorizonWmWindow {
+    pub id: u32,                // INFO: The X window id of the client.
     pub name: String,
-    pub icon: String,
-    // pub class: (String, String)  // NOTE: Unsure if we want this. Represents WM_CLASS prop tuple.
+    pub icon: String,           // INFO: Icon theme name guessed from the WM_CLASS, for windows without icons.
+    pub icons: Arc<[WmIcon]>,   // INFO: Every size of _NET_WM_ICON, replaced as a whole when the property changes.
+    pub class: (String, String),    // INFO: The (instance, class) tuple of the WM_CLASS property.
+    pub strut: Option<StrutPartialDef>, // INFO: Space reserved by docks and panels, see ewmh::get_strut.
+    pub window_type: WindowType,    // INFO: From _NET_WM_WINDOW_TYPE, clients without a known type are normal windows.
+    pub skip_taskbar: bool,
+    pub horizon_window: bool,   // INFO: Whether the client is a bar of any horizon process, tagged with _HORIZON_WINDOW.
     pub focused: bool,
     pub visible: bool,
     pub minimized: bool,
@@ -54,7 +73,387 @@ pub struct HorizonWmScratchpad {
 
 impl HorizonWm {
     fn new() -> Self {
-        todo!()
+        Self {
+            workspaces: Vec::new(),
+            scratchpads: Vec::new(),
+            window_ids: HashSet::new(),
+            new_windows: Vec::new(),
+        }
+    }
+
+    /// Reads the workspaces and their windows from the EWMH properties of the root window.
+    pub fn from_ewmh(connection: &impl Connection, atoms: &AtomCollection, root: Window) -> Result<Self, ReplyError> {
+        let count = ewmh::get_u32(connection, root, atoms._NET_NUMBER_OF_DESKTOPS)?.unwrap_or(1);
+        let names = ewmh::get_strings(connection, root, atoms._NET_DESKTOP_NAMES)?;
+        let current = ewmh::get_u32(connection, root, atoms._NET_CURRENT_DESKTOP)?.unwrap_or(0);
+        let active = ewmh::get_u32(connection, root, atoms._NET_ACTIVE_WINDOW)?.unwrap_or(0);
+        let clients = ewmh::get_u32_list(connection, root, atoms._NET_CLIENT_LIST)?;
+        let viewports = ewmh::get_u32_list(connection, root, atoms._NET_DESKTOP_VIEWPORT)?;
+
+        let mut workspaces = (0..count)
+            .map(|number| HorizonWmWorkspace::new(number, names.get(number as usize), number == current))
+            .collect::<Vec<_>>();
+
+        set_viewports(&mut workspaces, &viewports);
+
+        for client in clients {
+            // Clients can disappear while we are reading them, skip those.
+            let Ok(desktop) = ewmh::get_u32(connection, client, atoms._NET_WM_DESKTOP) else {
+                continue;
+            };
+            let desktop = desktop.unwrap_or(current);
+
+            match desktop {
+                ALL_DESKTOPS => {
+                    for workspace in workspaces.iter_mut() {
+                        if let Ok(window) = HorizonWmWindow::from_ewmh(connection, atoms, client, active, workspace.visible) {
+                            workspace.windows.push(window);
+                        }
+                    }
+                },
+                desktop => {
+                    let Some(workspace) = workspaces.get_mut(desktop as usize) else {
+                        continue;
+                    };
+
+                    if let Ok(window) = HorizonWmWindow::from_ewmh(connection, atoms, client, active, workspace.visible) {
+                        workspace.windows.push(window);
+                    }
+                },
+            }
+        }
+
+        let window_ids = workspaces
+            .iter()
+            .flat_map(|workspace| workspace.windows.iter().map(|window| window.id))
+            .collect::<HashSet<_>>();
+
+        Ok(Self {
+            workspaces,
+            scratchpads: Vec::new(),
+            new_windows: window_ids.iter().copied().collect(),
+            window_ids,
+        })
+    }
+
+    /// The X window ids of every client on any workspace.
+    pub fn window_ids(&self) -> &HashSet<Window> {
+        &self.window_ids
+    }
+
+    /// The clients added since the last call, all of them after [`HorizonWm::from_ewmh`].
+    pub fn take_new_windows(&mut self) -> Vec<Window> {
+        std::mem::take(&mut self.new_windows)
+    }
+
+    /// Applies a change of `property` on `window` (either the root window or a client).
+    ///
+    /// Only the affected workspaces and windows are re-read. Returns whether anything changed.
+    pub fn update_property(
+        &mut self,
+        connection: &impl Connection,
+        atoms: &AtomCollection,
+        root: Window,
+        window: Window,
+        property: Atom,
+    ) -> Result<bool, ReplyError> {
+        if window == root {
+            return self.update_root_property(connection, atoms, root, property);
+        }
+
+        if !self.window_ids.contains(&window) {
+            return Ok(false);
+        }
+
+        let is_name = property == atoms._NET_WM_NAME
+            || property == Atom::from(AtomEnum::WM_NAME)
+            || property == Atom::from(AtomEnum::WM_CLASS);
+        let is_state = property == atoms._NET_WM_STATE
+            || property == atoms._NET_WM_WINDOW_TYPE
+            || property == Atom::from(AtomEnum::WM_HINTS);
+        let is_strut = property == atoms._NET_WM_STRUT_PARTIAL || property == atoms._NET_WM_STRUT;
+
+        if is_name || is_state || is_strut {
+            let active = self.active_window();
+
+            for workspace in self.workspaces.iter_mut() {
+                let visible = workspace.visible;
+
+                for client in workspace.windows.iter_mut().filter(|client| client.id == window) {
+                    // A client destroyed in the meantime is removed along with _NET_CLIENT_LIST.
+                    let _ = client.refresh(connection, atoms, active, visible);
+                }
+            }
+
+            return Ok(true);
+        }
+
+        if property == atoms._NET_WM_ICON {
+            // Keeps the icons read before, e.g. when the client is already gone or its icons are too large to read.
+            let icons = match ewmh::get_wm_icons(connection, atoms, window) {
+                Ok(icons) => Arc::<[WmIcon]>::from(icons),
+                Err(err) => {
+                    debug!("Failed to read the icons of window {window}: {err}");
+                    return Ok(false);
+                },
+            };
+
+            for client in self.workspaces.iter_mut().flat_map(|workspace| workspace.windows.iter_mut()) {
+                if client.id == window {
+                    client.icons = icons.clone();
+                }
+            }
+
+            return Ok(true);
+        }
+
+        if property == atoms._NET_WM_DESKTOP {
+            self.remove_window(window);
+            self.insert_window(connection, atoms, window)?;
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    fn update_root_property(
+        &mut self,
+        connection: &impl Connection,
+        atoms: &AtomCollection,
+        root: Window,
+        property: Atom,
+    ) -> Result<bool, ReplyError> {
+        if property == atoms._NET_CURRENT_DESKTOP {
+            let current = ewmh::get_u32(connection, root, atoms._NET_CURRENT_DESKTOP)?.unwrap_or(0);
+
+            for workspace in self.workspaces.iter_mut() {
+                workspace.visible = workspace.number == current;
+
+                for window in workspace.windows.iter_mut() {
+                    window.visible = workspace.visible && !window.minimized;
+                }
+            }
+        } else if property == atoms._NET_ACTIVE_WINDOW {
+            let active = ewmh::get_u32(connection, root, atoms._NET_ACTIVE_WINDOW)?.unwrap_or(0);
+
+            for window in self.workspaces.iter_mut().flat_map(|workspace| workspace.windows.iter_mut()) {
+                window.focused = window.id == active;
+            }
+        } else if property == atoms._NET_DESKTOP_NAMES {
+            let names = ewmh::get_strings(connection, root, atoms._NET_DESKTOP_NAMES)?;
+
+            for workspace in self.workspaces.iter_mut() {
+                workspace.name = names
+                    .get(workspace.number as usize)
+                    .cloned()
+                    .unwrap_or_else(|| (workspace.number + 1).to_string());
+            }
+        } else if property == atoms._NET_DESKTOP_VIEWPORT {
+            let viewports = ewmh::get_u32_list(connection, root, atoms._NET_DESKTOP_VIEWPORT)?;
+            set_viewports(&mut self.workspaces, &viewports);
+        } else if property == atoms._NET_NUMBER_OF_DESKTOPS {
+            // Rare enough to simply start over.
+            *self = Self::from_ewmh(connection, atoms, root)?;
+        } else if property == atoms._NET_CLIENT_LIST {
+            let clients = ewmh::get_u32_list(connection, root, atoms._NET_CLIENT_LIST)?
+                .into_iter()
+                .collect::<HashSet<_>>();
+            let removed = self.window_ids
+                .difference(&clients)
+                .copied()
+                .collect::<Vec<_>>();
+
+            for window in removed {
+                self.remove_window(window);
+            }
+
+            let added = clients
+                .difference(&self.window_ids)
+                .copied()
+                .collect::<Vec<_>>();
+
+            for window in added {
+                self.insert_window(connection, atoms, window)?;
+            }
+        } else {
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// The struts of every client reserving space, by window id.
+    pub fn struts(&self) -> BTreeMap<Window, StrutPartialDef> {
+        self.workspaces
+            .iter()
+            .flat_map(|workspace| workspace.windows.iter())
+            .filter_map(|window| Some((window.id, window.strut.clone()?)))
+            .collect()
+    }
+
+    /// The struts of every client reserving space, except the bars of horizon, which are not stacked against.
+    pub fn foreign_struts(&self) -> Vec<StrutPartialDef> {
+        self.workspaces
+            .iter()
+            .flat_map(|workspace| workspace.windows.iter())
+            .filter(|window| !window.horizon_window)
+            .filter_map(|window| Some((window.id, window.strut.clone()?)))
+            // Sticky windows are on every workspace.
+            .collect::<BTreeMap<_, _>>()
+            .into_values()
+            .collect()
+    }
+
+    /// The client holding the input focus, if any.
+    pub fn focused_window(&self) -> Option<&HorizonWmWindow> {
+        self.workspaces
+            .iter()
+            .flat_map(|workspace| workspace.windows.iter())
+            .find(|window| window.focused)
+    }
+
+    fn active_window(&self) -> Window {
+        self.focused_window().map(|window| window.id).unwrap_or(0)
+    }
+
+    fn remove_window(&mut self, window: Window) {
+        for workspace in self.workspaces.iter_mut() {
+            workspace.windows.retain(|client| client.id != window);
+        }
+
+        self.window_ids.remove(&window);
+    }
+
+    /// Reads a client and adds it to the workspace(s) from its `_NET_WM_DESKTOP`.
+    /// Clients that already disappeared again are ignored.
+    fn insert_window(&mut self, connection: &impl Connection, atoms: &AtomCollection, window: Window) -> Result<(), ReplyError> {
+        let Ok(desktop) = ewmh::get_u32(connection, window, atoms._NET_WM_DESKTOP) else {
+            return Ok(());
+        };
+        let mut inserted = false;
+
+        let active = self.active_window();
+        let current = self.workspaces
+            .iter()
+            .find(|workspace| workspace.visible)
+            .map(|workspace| workspace.number)
+            .unwrap_or(0);
+
+        for workspace in self.workspaces.iter_mut() {
+            let on_workspace = match desktop.unwrap_or(current) {
+                ALL_DESKTOPS => true,
+                desktop => desktop == workspace.number,
+            };
+
+            if on_workspace {
+                if let Ok(client) = HorizonWmWindow::from_ewmh(connection, atoms, window, active, workspace.visible) {
+                    workspace.windows.push(client);
+                    inserted = true;
+                }
+            }
+        }
+
+        if inserted && self.window_ids.insert(window) {
+            self.new_windows.push(window);
+        }
+
+        Ok(())
+    }
+}
+
+impl HorizonWmWorkspace {
+    fn new(number: u32, name: Option<&String>, visible: bool) -> Self {
+        let name = name.cloned().unwrap_or_else(|| (number + 1).to_string());
+
+        Self {
+            name,
+            number,
+            icon: String::new(),
+            visible,
+            screen: None,
+            viewport: None,
+            layout: String::new(),
+            is_temp: false,
+            windows: Vec::new(),
+            layouts: Vec::new(),
+        }
+    }
+}
+
+/// `_NET_DESKTOP_VIEWPORT` holds an x, y pair per desktop.
+fn set_viewports(workspaces: &mut [HorizonWmWorkspace], viewports: &[u32]) {
+    for workspace in workspaces.iter_mut() {
+        let index = workspace.number as usize * 2;
+
+        workspace.viewport = match viewports.get(index..index + 2) {
+            Some([x, y]) => Some((*x as i32, *y as i32)),
+            _ => None,
+        };
     }
 }
 
+impl HorizonWmWindow {
+    /// Reads a client window. `active` is the value of `_NET_ACTIVE_WINDOW` and
+    /// `workspace_visible` whether the workspace holding the client is shown.
+    pub fn from_ewmh(
+        connection: &impl Connection,
+        atoms: &AtomCollection,
+        id: Window,
+        active: Window,
+        workspace_visible: bool,
+    ) -> Result<Self, ReplyError> {
+        let mut window = Self {
+            id,
+            name: String::new(),
+            icon: String::new(),
+            // Without readable icons, the theme icon from WM_CLASS is used.
+            icons: ewmh::get_wm_icons(connection, atoms, id).unwrap_or_default().into(),
+            class: (String::new(), String::new()),
+            strut: None,
+            window_type: WindowType::Normal,
+            skip_taskbar: false,
+            horizon_window: false,
+            focused: false,
+            visible: false,
+            minimized: false,
+            fullscreened: false,
+            urgent_flag_set: false,
+        };
+
+        window.refresh(connection, atoms, active, workspace_visible)?;
+        Ok(window)
+    }
+
+    /// Re-reads everything but the icons, which are only read again when `_NET_WM_ICON` changes.
+    fn refresh(
+        &mut self,
+        connection: &impl Connection,
+        atoms: &AtomCollection,
+        active: Window,
+        workspace_visible: bool,
+    ) -> Result<(), ReplyError> {
+        let name = ewmh::get_window_name(connection, atoms, self.id)?;
+        let states = ewmh::get_u32_list(connection, self.id, atoms._NET_WM_STATE)?;
+        let hints = ewmh::get_u32(connection, self.id, AtomEnum::WM_HINTS.into())?.unwrap_or(0);
+        let mut class = ewmh::get_strings(connection, self.id, AtomEnum::WM_CLASS.into())?.into_iter();
+        let strut = ewmh::get_strut(connection, atoms, self.id)?;
+        let types = ewmh::get_u32_list(connection, self.id, atoms._NET_WM_WINDOW_TYPE)?;
+        let horizon_window = ewmh::get_u32(connection, self.id, atoms._HORIZON_WINDOW)?.is_some();
+
+        self.name = name;
+        self.class = (class.next().unwrap_or_default(), class.next().unwrap_or_default());
+        self.icon = self.class.1.to_lowercase();
+        self.strut = strut;
+        self.window_type = WindowType::from_atoms(atoms, &types).unwrap_or(WindowType::Normal);
+        self.skip_taskbar = states.contains(&atoms._NET_WM_STATE_SKIP_TASKBAR);
+        self.horizon_window = horizon_window;
+        self.minimized = states.contains(&atoms._NET_WM_STATE_HIDDEN);
+        self.focused = self.id == active;
+        self.visible = workspace_visible && !self.minimized;
+        self.fullscreened = states.contains(&atoms._NET_WM_STATE_FULLSCREEN);
+        self.urgent_flag_set = states.contains(&atoms._NET_WM_STATE_DEMANDS_ATTENTION)
+            || hints & WM_HINTS_URGENCY != 0;
+
+        Ok(())
+    }
+}
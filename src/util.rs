@@ -1,4 +1,13 @@
-#[derive(Debug)]
+use std::env;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::de;
+use serde::{Deserialize, Deserializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Side {
     Left,
     Right,
@@ -6,20 +15,215 @@ pub enum Side {
     Bottom,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
 pub struct Size {
-    pub width: i32,
-    pub height: i32,
+    pub width: Length,
+    pub height: Length,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
 pub struct Position {
-    pub x: i32,
-    pub y: i32,
+    pub x: Length,
+    pub y: Length,
+}
+
+/// Space kept free around a window, inside its monitor.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Margins {
+    #[serde(default)]
+    pub top: Length,
+    #[serde(default)]
+    pub right: Length,
+    #[serde(default)]
+    pub bottom: Length,
+    #[serde(default)]
+    pub left: Length,
+}
+
+/// A length along one axis of a monitor, written as `30`, `"30px"`, `"50%"` or `"fill"` in the config.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Pixels(i32),
+    /// A percentage of the monitor along the same axis.
+    Percent(f64),
+    /// Whatever the other lengths on the same axis leave of the monitor.
+    Fill,
 }
 
-pub enum Number {
-    Absolute(i32),
-    Percent(i32),
+impl Length {
+    /// The length in pixels on an axis `total` pixels long, where other lengths already take `rest` pixels.
+    pub fn resolve(&self, total: i32, rest: i32) -> i32 {
+        match self {
+            Length::Pixels(pixels) => *pixels,
+            Length::Percent(percent) => (total as f64 * percent / 100.0).round() as i32,
+            Length::Fill => (total - rest).max(0),
+        }
+    }
 }
 
+impl Default for Length {
+    fn default() -> Self {
+        Length::Pixels(0)
+    }
+}
+
+impl From<i32> for Length {
+    fn from(pixels: i32) -> Self {
+        Length::Pixels(pixels)
+    }
+}
+
+impl FromStr for Length {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let invalid = || format!("Invalid length '{text}', expected pixels, a percentage or \"fill\"");
+
+        if text == "fill" {
+            return Ok(Length::Fill);
+        }
+
+        match text.strip_suffix('%') {
+            Some(percent) => percent.trim().parse().map(Length::Percent).map_err(|_| invalid()),
+            None => text.strip_suffix("px").unwrap_or(text).trim().parse().map(Length::Pixels).map_err(|_| invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Length {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Length::Pixels(pixels) => write!(f, "{pixels}px"),
+            Length::Percent(percent) => write!(f, "{percent}%"),
+            Length::Fill => write!(f, "fill"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Length {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawLength {
+            Pixels(i32),
+            Text(String),
+        }
+
+        match RawLength::deserialize(deserializer)? {
+            RawLength::Pixels(pixels) => Ok(Length::Pixels(pixels)),
+            RawLength::Text(text) => text.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// The offset and size along one axis of a monitor `total` pixels long, relative to the monitor.
+///
+/// A `fill` size takes what the position and margins leave, a `fill` position moves the window to the far end.
+pub fn resolve_axis(total: i32, position: Length, size: Length, margin_start: Length, margin_end: Length) -> (i32, i32) {
+    let margin_start = margin_start.resolve(total, 0);
+    let margins = margin_start + margin_end.resolve(total, 0);
+
+    let offset = match position {
+        Length::Fill => 0,
+        position => position.resolve(total, 0),
+    };
+
+    let size = size.resolve(total, margins + offset);
+    let offset = match position {
+        Length::Fill => position.resolve(total, margins + size),
+        _ => offset,
+    };
+
+    (margin_start + offset, size)
+}
+
+/// `path` with a leading `~` replaced by the home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix("~/") {
+        Some(rest) => PathBuf::from(env::var_os("HOME").unwrap_or_default()).join(rest),
+        None => PathBuf::from(path),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Lengths {
+        size: Length,
+        span: (Length, Length),
+    }
+
+    #[test]
+    fn parse_lengths() {
+        assert_eq!("30".parse::<Length>(), Ok(Length::Pixels(30)));
+        assert_eq!("30px".parse::<Length>(), Ok(Length::Pixels(30)));
+        assert_eq!(" -4 px ".parse::<Length>(), Ok(Length::Pixels(-4)));
+        assert_eq!("50%".parse::<Length>(), Ok(Length::Percent(50.0)));
+        assert_eq!("12.5 %".parse::<Length>(), Ok(Length::Percent(12.5)));
+        assert_eq!("fill".parse::<Length>(), Ok(Length::Fill));
+
+        for invalid in ["", "px", "%", "30em", "half", "Fill", "30.5px"] {
+            assert!(invalid.parse::<Length>().is_err(), "{invalid:?} should not parse");
+        }
+    }
+
+    #[test]
+    fn deserialize_lengths() {
+        let lengths: Lengths = toml::from_str(r#"
+            size = 30
+            span = ["50%", "fill"]
+        "#).unwrap();
+
+        assert_eq!(lengths.size, Length::Pixels(30));
+        assert_eq!(lengths.span, (Length::Percent(50.0), Length::Fill));
+
+        assert!(toml::from_str::<Lengths>(r#"size = "wide"
+            span = [0, 0]"#).is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for length in [Length::Pixels(30), Length::Percent(12.5), Length::Fill] {
+            assert_eq!(length.to_string().parse::<Length>(), Ok(length));
+        }
+    }
+
+    #[test]
+    fn resolve_lengths() {
+        assert_eq!(Length::Pixels(30).resolve(1920, 100), 30);
+        assert_eq!(Length::Percent(50.0).resolve(1920, 100), 960);
+        assert_eq!(Length::Percent(33.3).resolve(100, 0), 33);
+        assert_eq!(Length::Fill.resolve(1920, 100), 1820);
+        assert_eq!(Length::Fill.resolve(1920, 2000), 0);
+    }
+
+    #[test]
+    fn resolve_axis_with_fill_size() {
+        assert_eq!(resolve_axis(1920, Length::Pixels(0), Length::Fill, Length::Pixels(0), Length::Pixels(0)), (0, 1920));
+        assert_eq!(resolve_axis(1920, Length::Percent(10.0), Length::Fill, Length::Pixels(8), Length::Pixels(8)), (200, 1712));
+    }
+
+    #[test]
+    fn resolve_axis_with_fill_position() {
+        // Moved to the far end, inside the end margin.
+        assert_eq!(resolve_axis(1920, Length::Fill, Length::Percent(50.0), Length::Pixels(0), Length::Pixels(8)), (952, 960));
+        assert_eq!(resolve_axis(1080, Length::Fill, Length::Pixels(40), Length::Pixels(0), Length::Pixels(0)), (1040, 40));
+    }
+
+    #[test]
+    fn resolve_axis_with_percent_margins() {
+        assert_eq!(resolve_axis(2000, Length::Pixels(0), Length::Fill, Length::Percent(5.0), Length::Percent(5.0)), (100, 1800));
+        assert_eq!(resolve_axis(2000, Length::Fill, Length::Pixels(300), Length::Percent(5.0), Length::Percent(10.0)), (1500, 300));
+    }
+
+    #[test]
+    fn resolve_strut_span() {
+        let (start, end): (Length, Length) = ("50%".parse().unwrap(), "fill".parse().unwrap());
+
+        assert_eq!((start.resolve(2560, 0), end.resolve(2560, 0)), (1280, 2560));
+    }
+}
@@ -0,0 +1,195 @@
+use std::ffi::OsStr;
+use std::rc::{Rc, Weak};
+
+use gio::{Subprocess, SubprocessFlags};
+use glib::{clone, Propagation};
+use gtk::prelude::*;
+use gtk::{EventControllerMotion, EventControllerScroll, EventControllerScrollFlags, GestureClick, PropagationPhase};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::event::{EVENT_MANAGER, Event};
+use crate::widgets::HorizonWidget;
+
+/// What happens when the user interacts with a widget.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// Runs a command through `sh -c`, without waiting for it.
+    Command(String),
+    /// Publishes [`Event::Custom`] with the given name.
+    Event(String),
+    /// Shows or hides the popup of the widget.
+    Popup,
+    /// Switches the widget between its normal and alternate view, e.g. the formats of a clock.
+    Toggle,
+    /// Moves to the next item of a widget showing one of several, e.g. the timezones of a clock.
+    Next,
+    /// Moves to the previous item, see [`Action::Next`].
+    Previous,
+}
+
+/// The `actions` table of a widget. Every key is optional.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WidgetActions {
+    #[serde(default)]
+    pub left_click: Option<Action>,
+    #[serde(default)]
+    pub middle_click: Option<Action>,
+    #[serde(default)]
+    pub right_click: Option<Action>,
+    // NOTE: The first click of a double click also runs left_click.
+    #[serde(default)]
+    pub double_click: Option<Action>,
+    #[serde(default)]
+    pub scroll_up: Option<Action>,
+    #[serde(default)]
+    pub scroll_down: Option<Action>,
+    #[serde(default)]
+    pub hover_enter: Option<Action>,
+    #[serde(default)]
+    pub hover_leave: Option<Action>,
+}
+
+impl Action {
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Action::Command(command) if command.trim().is_empty() => Err(String::from("Action has an empty command")),
+            Action::Event(name) if name.is_empty() => Err(String::from("Action has an empty event name")),
+            _ => Ok(()),
+        }
+    }
+
+    fn run(&self, widget: &Weak<dyn HorizonWidget>) {
+        match self {
+            Action::Command(command) => {
+                let argv = [OsStr::new("sh"), OsStr::new("-c"), OsStr::new(command)];
+
+                // The subprocess is reaped by gio once it exits.
+                if let Err(err) = Subprocess::newv(&argv, SubprocessFlags::NONE) {
+                    warn!("Failed to run '{command}': {err}");
+                }
+            },
+            Action::Event(name) => EVENT_MANAGER.notify_listeners(Event::Custom(name.clone())),
+            Action::Popup => {
+                let Some(popover) = widget.upgrade().and_then(|widget| widget.popup()) else {
+                    debug!("Widget has no popup to toggle");
+                    return;
+                };
+
+                match popover.is_visible() {
+                    true => popover.popdown(),
+                    false => popover.popup(),
+                }
+            },
+            Action::Toggle | Action::Next | Action::Previous => {
+                let Some(widget) = widget.upgrade() else {
+                    return;
+                };
+
+                match self {
+                    Action::Next => widget.cycle(1),
+                    Action::Previous => widget.cycle(-1),
+                    _ => widget.toggle(),
+                }
+            },
+        }
+    }
+}
+
+impl WidgetActions {
+    /// These actions, with the unset ones taken from `defaults`.
+    pub fn or(&self, defaults: &WidgetActions) -> Self {
+        let or = |action: &Option<Action>, default: &Option<Action>| action.clone().or_else(|| default.clone());
+
+        Self {
+            left_click: or(&self.left_click, &defaults.left_click),
+            middle_click: or(&self.middle_click, &defaults.middle_click),
+            right_click: or(&self.right_click, &defaults.right_click),
+            double_click: or(&self.double_click, &defaults.double_click),
+            scroll_up: or(&self.scroll_up, &defaults.scroll_up),
+            scroll_down: or(&self.scroll_down, &defaults.scroll_down),
+            hover_enter: or(&self.hover_enter, &defaults.hover_enter),
+            hover_leave: or(&self.hover_leave, &defaults.hover_leave),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        [
+            &self.left_click, &self.middle_click, &self.right_click, &self.double_click,
+            &self.scroll_up, &self.scroll_down, &self.hover_enter, &self.hover_leave,
+        ]
+            .into_iter()
+            .flatten()
+            .try_for_each(Action::validate)
+    }
+
+    /// Adds the event controllers running the configured actions to the root widget of `widget`.
+    ///
+    /// Controllers are only added for configured actions, so widgets keep handling the rest themselves.
+    pub fn attach(&self, root: &gtk::Widget, widget: &Rc<dyn HorizonWidget>) {
+        let widget = Rc::downgrade(widget);
+        let clicks = [&self.left_click, &self.middle_click, &self.right_click, &self.double_click];
+
+        if clicks.iter().any(|action| action.is_some()) {
+            let gesture = GestureClick::new();
+            gesture.set_button(0);  // Listen for all buttons
+            // Runs before the widget's own gestures, e.g. those of a button.
+            gesture.set_propagation_phase(PropagationPhase::Capture);
+
+            let actions = self.clone();
+            gesture.connect_pressed(clone!(@strong widget => move |gesture, n_press, _, _| {
+                let action = match (gesture.current_button(), n_press) {
+                    (gtk::gdk::BUTTON_PRIMARY, 2) => &actions.double_click,
+                    (gtk::gdk::BUTTON_PRIMARY, _) => &actions.left_click,
+                    (gtk::gdk::BUTTON_MIDDLE, _) => &actions.middle_click,
+                    (gtk::gdk::BUTTON_SECONDARY, _) => &actions.right_click,
+                    _ => &None,
+                };
+
+                if let Some(action) = action {
+                    action.run(&widget);
+                }
+            }));
+
+            root.add_controller(gesture);
+        }
+
+        if self.scroll_up.is_some() || self.scroll_down.is_some() {
+            let scroll = EventControllerScroll::new(EventControllerScrollFlags::VERTICAL | EventControllerScrollFlags::DISCRETE);
+
+            let actions = self.clone();
+            scroll.connect_scroll(clone!(@strong widget => move |_, _, dy| {
+                let action = match dy < 0.0 {
+                    true => &actions.scroll_up,
+                    false => &actions.scroll_down,
+                };
+
+                match action {
+                    Some(action) => {
+                        action.run(&widget);
+                        Propagation::Stop
+                    },
+                    None => Propagation::Proceed,
+                }
+            }));
+
+            root.add_controller(scroll);
+        }
+
+        if self.hover_enter.is_some() || self.hover_leave.is_some() {
+            let motion = EventControllerMotion::new();
+
+            if let Some(action) = self.hover_enter.clone() {
+                motion.connect_enter(clone!(@strong widget => move |_, _, _| action.run(&widget)));
+            }
+
+            if let Some(action) = self.hover_leave.clone() {
+                motion.connect_leave(clone!(@strong widget => move |_| action.run(&widget)));
+            }
+
+            root.add_controller(motion);
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use chrono::{Datelike, Days, Local, Months, NaiveDate};
+use glib::clone;
+use gtk::pango::EllipsizeMode;
+use gtk::prelude::*;
+use gtk::{Button, Grid};
+
+use crate::state::*;
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Summaries in the agenda are ellipsized past this many characters.
+const SUMMARY_LENGTH: i32 = 40;
+
+/// A month view with ISO week numbers, used as the popup of the Clock.
+///
+/// Below the month, the events of the [`AGENDA`] for the next `agenda_days` days are listed.
+pub struct Calendar {
+    container: gtk::Box,
+    title: Button,
+    grid: Grid,
+    agenda: gtk::Box,
+    agenda_days: u64,
+    /// The first day of the month shown.
+    shown: Cell<NaiveDate>,
+}
+
+impl Calendar {
+    pub fn new(agenda_days: u64) -> Rc<Self> {
+        let container = gtk::Box::new(gtk::Orientation::Vertical, 4);
+        container.add_css_class("calendar");
+
+        let header = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        header.add_css_class("calendar-header");
+
+        let previous = Button::from_icon_name("go-previous-symbolic");
+        let next = Button::from_icon_name("go-next-symbolic");
+        let title = Button::new();
+        title.set_hexpand(true);
+        title.set_tooltip_text(Some("Back to today"));
+
+        header.append(&previous);
+        header.append(&title);
+        header.append(&next);
+
+        let grid = Grid::new();
+        grid.set_column_homogeneous(true);
+
+        let agenda = gtk::Box::new(gtk::Orientation::Vertical, 2);
+        agenda.add_css_class("agenda");
+        agenda.set_visible(agenda_days > 0);
+
+        container.append(&header);
+        container.append(&grid);
+        container.append(&agenda);
+
+        let calendar = Rc::new(Self {
+            container,
+            title,
+            grid,
+            agenda,
+            agenda_days,
+            shown: Cell::new(today()),
+        });
+
+        previous.connect_clicked(clone!(@weak calendar => move |_| {
+            calendar.show_month(calendar.shown.get() - Months::new(1));
+        }));
+
+        next.connect_clicked(clone!(@weak calendar => move |_| {
+            calendar.show_month(calendar.shown.get() + Months::new(1));
+        }));
+
+        calendar.title.connect_clicked(clone!(@weak calendar => move |_| calendar.show_today()));
+
+        calendar.show_today();
+        calendar
+    }
+
+    pub fn widget(&self) -> gtk::Box {
+        self.container.clone()
+    }
+
+    /// Shows the current month, with the current day highlighted, and the upcoming events.
+    pub fn show_today(&self) {
+        self.show_month(today());
+        self.show_agenda();
+    }
+
+    /// Shows the month containing `date`.
+    fn show_month(&self, date: NaiveDate) {
+        let first = date.with_day(1).unwrap_or(date);
+        self.shown.set(first);
+
+        let month = HorizonDate::from_date(&first);
+        self.title.set_label(&format!("{} {}", month.month.name, month.year));
+
+        while let Some(child) = self.grid.first_child() {
+            self.grid.remove(&child);
+        }
+
+        let week_header = gtk::Label::new(Some("Wk"));
+        week_header.add_css_class("week-number");
+        self.grid.attach(&week_header, 0, 0, 1, 1);
+
+        for (column, name) in WEEKDAYS.iter().enumerate() {
+            let weekday = gtk::Label::new(Some(name));
+            weekday.add_css_class("weekday-name");
+            self.grid.attach(&weekday, column as i32 + 1, 0, 1, 1);
+        }
+
+        let today = today();
+        let agenda = AGENDA.read().unwrap();
+        let start = first - Days::new(first.weekday().num_days_from_monday() as u64);
+        let last = first + Months::new(1) - Days::new(1);
+
+        for (row, monday) in start.iter_weeks().take_while(|monday| *monday <= last).enumerate() {
+            let row = row as i32 + 1;
+
+            let week = gtk::Label::new(Some(&HorizonDate::from_date(&monday).day.week_number.to_string()));
+            week.add_css_class("week-number");
+            self.grid.attach(&week, 0, row, 1, 1);
+
+            for (column, date) in monday.iter_days().take(7).enumerate() {
+                let day = HorizonDate::from_date(&date);
+
+                let label = gtk::Label::new(Some(&day.day.day_of_month.to_string()));
+                label.add_css_class("day");
+                label.set_tooltip_text(Some(&format!("Day {} of the year", day.day.day_of_year)));
+
+                label.add_css_class(match day.day.day_type {
+                    DayType::Weekday => "weekday",
+                    DayType::Weekend => "weekend",
+                });
+
+                if date.month() != first.month() {
+                    label.add_css_class("other-month");
+                }
+
+                if date == today {
+                    label.add_css_class("today");
+                }
+
+                if agenda.has_events_on(date) {
+                    label.add_css_class("has-events");
+                }
+
+                self.grid.attach(&label, column as i32 + 1, row, 1, 1);
+            }
+        }
+    }
+
+    /// Lists the events of the next `agenda_days` days, grouped by day.
+    fn show_agenda(&self) {
+        if self.agenda_days == 0 {
+            return;
+        }
+
+        while let Some(child) = self.agenda.first_child() {
+            self.agenda.remove(&child);
+        }
+
+        let now = Local::now();
+        let today = now.date_naive();
+        let until = local_midnight(today + Days::new(self.agenda_days));
+        let agenda = AGENDA.read().unwrap();
+        let mut shown_day = None;
+
+        for event in agenda.upcoming(now, until) {
+            // Events that started before today are listed under today.
+            let day = event.start.date_naive().max(today);
+
+            if shown_day != Some(day) {
+                shown_day = Some(day);
+
+                let header = gtk::Label::new(Some(&day_name(day, today)));
+                header.add_css_class("agenda-day");
+                header.set_xalign(0.0);
+                self.agenda.append(&header);
+            }
+
+            let time = match event.all_day {
+                true => String::from("All day"),
+                false => event.start.format("%H:%M").to_string(),
+            };
+
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 8);
+            row.add_css_class("agenda-event");
+
+            let time = gtk::Label::new(Some(&time));
+            time.add_css_class("agenda-time");
+
+            let summary = gtk::Label::new(Some(&event.summary));
+            summary.add_css_class("agenda-summary");
+            summary.set_xalign(0.0);
+            summary.set_max_width_chars(SUMMARY_LENGTH);
+            summary.set_ellipsize(EllipsizeMode::End);
+
+            let tooltip = match &event.location {
+                Some(location) => format!("{}\n{location}\n{}", event.summary, event.calendar),
+                None => format!("{}\n{}", event.summary, event.calendar),
+            };
+            row.set_tooltip_text(Some(&tooltip));
+
+            row.append(&time);
+            row.append(&summary);
+            self.agenda.append(&row);
+        }
+
+        if shown_day.is_none() {
+            let empty = gtk::Label::new(Some("No upcoming events"));
+            empty.add_css_class("agenda-empty");
+            self.agenda.append(&empty);
+        }
+    }
+}
+
+/// `Today`, `Tomorrow` or e.g. `Friday, Jan 5`.
+fn day_name(day: NaiveDate, today: NaiveDate) -> String {
+    match (day - today).num_days() {
+        0 => String::from("Today"),
+        1 => String::from("Tomorrow"),
+        _ => day.format("%A, %b %-d").to_string(),
+    }
+}
+
+fn today() -> NaiveDate {
+    DATETIME.read().unwrap().now.0.date_naive()
+}
@@ -1,95 +1,240 @@
-use std::sync::RwLock;
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::rc::Rc;
 
-use glib::{clone, ControlFlow};
+use chrono::format::{Fixed, Item, Numeric, StrftimeItems};
 use gtk::prelude::*;
-use gtk::{Button, GestureClick};
-use tokio::sync::watch::Receiver;
+use gtk::Button;
+use serde::Deserialize;
 
+use crate::event::Event;
 use crate::state::*;
+use crate::widgets::{Action, Calendar, FromWidgetConfig, HorizonWidget, WidgetActions, WidgetContext};
+
+/// Days of upcoming events listed below the calendar.
+const DEFAULT_AGENDA_DAYS: u64 = 7;
+
+/// The format the clock had before formats were configurable, e.g. `Mon, Jan 1 | 9:05:00`.
+const DEFAULT_FORMAT: &str = "%a, %b %-d | %-H:%M:%S";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClockOptions {
+    /// A chrono strftime format.
+    #[serde(default = "default_format")]
+    format: String,
+    /// Shown instead of `format` after a toggle action, by default on right click.
+    #[serde(default)]
+    alt_format: Option<String>,
+    /// IANA timezone names, or `local`. Defaults to the local timezone only.
+    #[serde(default)]
+    timezones: Vec<String>,
+    /// Show one timezone at a time, switching on scroll, instead of all of them.
+    #[serde(default)]
+    cycle_timezones: bool,
+    /// Put between the timezones when they are all shown.
+    #[serde(default = "default_separator")]
+    separator: String,
+    /// `.ics` files or vdir directories whose events are listed in the popup.
+    #[serde(default)]
+    calendars: Vec<String>,
+    #[serde(default = "default_agenda_days")]
+    agenda_days: u64,
+}
 
 pub struct Clock {
     gtk_widget: Button,
-    receiver: Receiver<ChannelMessage>,
-    left_click_action: Box<dyn Fn() -> ()>,
-    middle_click_action: Box<dyn Fn() -> ()>,
-    right_click_action: Box<dyn Fn() -> ()>,
+    /// Shows a [`Calendar`] of the current month, by default on left click.
+    popover: gtk::Popover,
+    // NOTE: The buttons of the calendar only hold weak references to it.
+    calendar: Rc<Calendar>,
+    format: String,
+    alt_format: Option<String>,
+    showing_alt: Cell<bool>,
+    timezones: Vec<HorizonTimezone>,
+    cycle_timezones: bool,
+    /// Index into `timezones` of the one shown while cycling.
+    current_timezone: Cell<usize>,
+    separator: String,
+    /// Whether any of the formats shows seconds, see [`HorizonDateTime::require_seconds`].
+    uses_seconds: bool,
+    calendars: Vec<String>,
 }
 
-impl Clock {
-    pub fn new() -> Self {
-        let receiver = DATETIME.read().unwrap().sender.subscribe();
-
-        let button = Button::builder()
-            .label(Clock::formatted_time())
-            .build();
-
-        let mut rx = receiver.clone();
-        glib::spawn_future_local(clone!(@strong button => async move {
-            while rx.changed().await.is_ok() {
-                match *rx.borrow_and_update() {
-                    ChannelMessage::Init => {},
-                    ChannelMessage::Updated => {
-                        button.set_label(&Clock::formatted_time());
-                    },
-                }
-            }
-        }));
+fn default_format() -> String {
+    String::from(DEFAULT_FORMAT)
+}
 
-        let click_gesture = GestureClick::new();
-        click_gesture.set_button(0);  // Listen for all buttons
+fn default_separator() -> String {
+    String::from(" | ")
+}
 
-        click_gesture.connect_pressed(clone!(@weak click_gesture => move |_, _, _, _| {
-            match click_gesture.current_button() {
-                gtk::gdk::BUTTON_PRIMARY => println!("Left click"),   // Left click
-                gtk::gdk::BUTTON_MIDDLE => println!("Middle click"), // Middle click
-                gtk::gdk::BUTTON_SECONDARY => println!("Right click"),  // Right click
-                _ => (),
+fn default_agenda_days() -> u64 {
+    DEFAULT_AGENDA_DAYS
+}
+
+impl FromWidgetConfig for Clock {
+    const NAME: &'static str = "clock";
+    type Options = ClockOptions;
+
+    fn validate(options: &ClockOptions, _states: &HashSet<String>) -> Result<(), String> {
+        let formats = [Some(&options.format), options.alt_format.as_ref()];
+
+        for format in formats.into_iter().flatten() {
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(format!("Clock widget has an invalid format '{format}'"));
             }
-        }));
+        }
 
-        button.add_controller(click_gesture);
+        options.timezones
+            .iter()
+            .try_for_each(|timezone| timezone.parse::<HorizonTimezone>().map(|_| ()))
+            .map_err(|err| format!("Clock widget: {err}"))
+    }
+
+    fn new(context: &WidgetContext, options: ClockOptions) -> Self {
+        let uses_seconds = shows_seconds(&options.format)
+            || options.alt_format.as_deref().is_some_and(shows_seconds);
+
+        if uses_seconds {
+            DATETIME.write().unwrap().require_seconds();
+        }
+
+        let mut timezones = options.timezones
+            .iter()
+            .filter_map(|timezone| timezone.parse().ok())
+            .collect::<Vec<_>>();
 
-        Self {
-            gtk_widget: button,
-            receiver,
-            left_click_action: Box::new(|| println!("Left click")),
-            middle_click_action: Box::new(|| println!("Middle click")),
-            right_click_action: Box::new(|| println!("Right click")),
+        if timezones.is_empty() {
+            timezones.push(HorizonTimezone::Local);
         }
+
+        DATETIME.write().unwrap().require_timezones(&timezones);
+
+        AGENDA.write().unwrap().add_sources(&options.calendars);
+
+        let agenda_days = match options.calendars.is_empty() {
+            true => 0,
+            false => options.agenda_days,
+        };
+
+        let gtk_widget = Button::new();
+        let calendar = Calendar::new(agenda_days);
+
+        let popover = gtk::Popover::new();
+        popover.set_child(Some(&calendar.widget()));
+        popover.set_parent(&gtk_widget);
+        popover.add_css_class("calendar-popover");
+
+        // Opening the calendar always starts from the current month.
+        popover.connect_show(glib::clone!(@weak calendar => move |_| calendar.show_today()));
+
+        let clock = Self {
+            gtk_widget,
+            popover,
+            calendar,
+            format: options.format,
+            alt_format: options.alt_format,
+            showing_alt: Cell::new(false),
+            timezones,
+            cycle_timezones: options.cycle_timezones,
+            current_timezone: Cell::new(0),
+            separator: options.separator,
+            uses_seconds,
+            calendars: options.calendars,
+        };
+
+        clock.gtk_widget.add_css_class("clock");
+        clock.update(&Event::HorizonDateTimeUpdated);
+        clock
     }
+}
 
-    pub fn set_left_click_action<F: Fn() -> () + 'static>(&mut self, action: F) {
-        self.left_click_action = Box::new(action);
+impl HorizonWidget for Clock {
+    fn widget(&self) -> gtk::Widget {
+        self.gtk_widget.clone().upcast()
     }
 
-    pub fn set_middle_click_action<F: Fn() -> () + 'static>(&mut self, action: F) {
-        self.middle_click_action = Box::new(action);
+    fn topics(&self) -> Vec<Event> {
+        vec![Event::HorizonDateTimeUpdated]
     }
 
-    pub fn set_right_click_action<F: Fn() -> () + 'static>(&mut self, action: F) {
-        self.right_click_action = Box::new(action);
+    fn update(&self, _event: &Event) {
+        let format = match (self.showing_alt.get(), &self.alt_format) {
+            (true, Some(alt_format)) => alt_format,
+            _ => &self.format,
+        };
+
+        let datetime = DATETIME.read().unwrap();
+
+        let label = match self.cycle_timezones {
+            true => datetime.format_in(&self.timezones[self.current_timezone.get()], format),
+            false => self.timezones
+                .iter()
+                .map(|timezone| datetime.format_in(timezone, format))
+                .collect::<Vec<_>>()
+                .join(&self.separator),
+        };
+
+        self.gtk_widget.set_label(&label);
     }
 
-    pub fn widget(self) -> Button {
-        self.gtk_widget
+    fn popup(&self) -> Option<gtk::Popover> {
+        Some(self.popover.clone())
     }
 
-    fn formatted_time() -> String {
-        let datetime = DATETIME.read().unwrap();
-        format!(
-            "{}, {} {} | {}:{:02}:{:02}",
-            datetime.date.day.short_name,
-            datetime.date.month.short_name,
-            datetime.date.day.day_of_month,
-            datetime.time.hour,
-            datetime.time.minute,
-            datetime.time.second,
-        )
+    fn toggle(&self) {
+        if self.alt_format.is_some() {
+            self.showing_alt.set(!self.showing_alt.get());
+            self.update(&Event::HorizonDateTimeUpdated);
+        }
+    }
+
+    fn cycle(&self, step: isize) {
+        let count = self.timezones.len() as isize;
+        let current = (self.current_timezone.get() as isize + step).rem_euclid(count);
+
+        self.current_timezone.set(current as usize);
+        self.update(&Event::HorizonDateTimeUpdated);
+    }
+
+    fn default_actions(&self) -> WidgetActions {
+        let cycles = self.cycle_timezones && self.timezones.len() > 1;
+
+        WidgetActions {
+            left_click: Some(Action::Popup),
+            right_click: self.alt_format.as_ref().map(|_| Action::Toggle),
+            scroll_up: cycles.then_some(Action::Previous),
+            scroll_down: cycles.then_some(Action::Next),
+            ..WidgetActions::default()
+        }
+    }
+
+    fn teardown(&self) {
+        self.popover.unparent();
+        AGENDA.write().unwrap().remove_sources(&self.calendars);
+
+        let mut datetime = DATETIME.write().unwrap();
+        datetime.release_timezones(&self.timezones);
+
+        if self.uses_seconds {
+            datetime.release_seconds();
+        }
     }
 }
 
-// impl UsesHorizonData for Clock {
-//     fn update(&self) {
-//         self.gtk_widget.set_label(&self.data.read().unwrap().to_string());
-//     }
-// }
\ No newline at end of file
+/// Whether `format` changes more often than once a minute.
+fn shows_seconds(format: &str) -> bool {
+    StrftimeItems::new(format).any(|item| matches!(
+        item,
+        Item::Numeric(Numeric::Second | Numeric::Nanosecond | Numeric::Timestamp, _)
+            | Item::Fixed(
+                Fixed::Nanosecond
+                    | Fixed::Nanosecond3
+                    | Fixed::Nanosecond6
+                    | Fixed::Nanosecond9
+                    | Fixed::RFC2822
+                    | Fixed::RFC3339
+            )
+    ))
+}
@@ -0,0 +1,77 @@
+use std::collections::HashSet;
+
+use gtk::prelude::*;
+use serde::Deserialize;
+
+use crate::config::WidgetConfig;
+use crate::widgets::{validate_widget, FromWidgetConfig, HorizonWidget, MountedWidget, WidgetContext};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    #[default]
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContainerOptions {
+    #[serde(default)]
+    orientation: Orientation,
+    #[serde(default)]
+    spacing: i32,
+    #[serde(default)]
+    children: Vec<WidgetConfig>,
+}
+
+/// Lays out other widgets in a row or column, the `box` widget of the config.
+pub struct Container {
+    gtk_widget: gtk::Box,
+    children: Vec<MountedWidget>,
+}
+
+impl From<Orientation> for gtk::Orientation {
+    fn from(orientation: Orientation) -> Self {
+        match orientation {
+            Orientation::Horizontal => gtk::Orientation::Horizontal,
+            Orientation::Vertical => gtk::Orientation::Vertical,
+        }
+    }
+}
+
+impl FromWidgetConfig for Container {
+    const NAME: &'static str = "box";
+    type Options = ContainerOptions;
+
+    fn validate(options: &ContainerOptions, states: &HashSet<String>) -> Result<(), String> {
+        options.children.iter().try_for_each(|child| validate_widget(child, states))
+    }
+
+    fn new(context: &WidgetContext, options: ContainerOptions) -> Self {
+        let container = gtk::Box::builder()
+            .orientation(options.orientation.into())
+            .spacing(options.spacing)
+            .build();
+
+        let children = options.children
+            .iter()
+            .filter_map(|child| MountedWidget::build(context, child))
+            .collect::<Vec<_>>();
+
+        for child in children.iter() {
+            container.append(child.root());
+        }
+
+        Self {
+            gtk_widget: container,
+            children,
+        }
+    }
+}
+
+impl HorizonWidget for Container {
+    fn widget(&self) -> gtk::Widget {
+        self.gtk_widget.clone().upcast()
+    }
+}
@@ -1,25 +1,118 @@
-use gtk::{Button, Widget};
+use std::collections::HashSet;
+use std::path::Path;
+
+use gtk::gdk_pixbuf::Pixbuf;
+use gtk::prelude::*;
+use gtk::{gdk, pango, Widget};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::widgets::{FromWidgetConfig, HorizonWidget, WidgetContext};
+
+/// Shown in place of image files that fail to load.
+const MISSING_ICON: &str = "image-missing";
+
+/// Glyphs are drawn at this fraction of the icon size, which leaves room for the line spacing of the font.
+const GLYPH_SCALE: f64 = 0.7;
+
+/// Where the image of an [`Icon`] comes from.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IconSource {
+    /// A freedesktop icon theme name, e.g. `audio-volume-high`.
+    Theme(String),
+    /// An image file in any format gdk-pixbuf can load, e.g. PNG or SVG.
+    File(String),
+    /// Text drawn as an icon, e.g. a Nerd Font glyph.
+    Glyph(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IconOptions {
+    icon: IconSource,
+    /// Height in pixels, defaults to the height of the window.
+    #[serde(default)]
+    size: Option<i32>,
+}
 
 pub struct Icon {
     gtk_widget: Widget,
 }
 
-impl Icon {
-    pub fn new(icon: &str) -> Self {
-        let icon = Button::builder()
-            .label(icon.to_owned())
-            .build();
+impl FromWidgetConfig for Icon {
+    const NAME: &'static str = "icon";
+    type Options = IconOptions;
+
+    fn validate(options: &IconOptions, _states: &HashSet<String>) -> Result<(), String> {
+        if options.size.is_some_and(|size| size <= 0) {
+            return Err(String::from("Icon widget must have a positive size"));
+        }
+
+        match &options.icon {
+            IconSource::Theme(name) | IconSource::Glyph(name) if name.is_empty() => {
+                Err(String::from("Icon widget has an empty icon"))
+            },
+            IconSource::File(path) if !Path::new(path).is_file() => {
+                Err(format!("Icon widget uses '{path}', which is not a file"))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn new(context: &WidgetContext, options: IconOptions) -> Self {
+        let size = options.size.unwrap_or(context.height);
+
+        let (icon, class): (Widget, _) = match &options.icon {
+            IconSource::Theme(name) => (Icon::themed(name, size).upcast(), "icon-theme"),
+            IconSource::File(path) => (Icon::file(Path::new(path), size).upcast(), "icon-file"),
+            IconSource::Glyph(glyph) => (Icon::glyph(glyph, size).upcast(), "icon-glyph"),
+        };
+
+        icon.add_css_class("icon");
+        icon.add_css_class(class);
 
         Self {
-            gtk_widget: icon.into(),
+            gtk_widget: icon,
         }
     }
+}
 
-    pub fn widget(self) -> Widget {
-        self.gtk_widget
+impl HorizonWidget for Icon {
+    fn widget(&self) -> Widget {
+        self.gtk_widget.clone()
     }
+}
 
-    // pub fn as_button(self) -> Option<Button> {
-    //     self.gtk_widget.downcast::<Button>()
-    // }
+impl Icon {
+    fn themed(name: &str, size: i32) -> gtk::Image {
+        let image = gtk::Image::from_icon_name(name);
+        image.set_pixel_size(size);
+        image
+    }
+
+    fn file(path: &Path, size: i32) -> gtk::Image {
+        // Loading at the final size keeps SVGs sharp.
+        match Pixbuf::from_file_at_scale(path, -1, size, true) {
+            Ok(pixbuf) => {
+                let image = gtk::Image::from_paintable(Some(&gdk::Texture::for_pixbuf(&pixbuf)));
+                image.set_pixel_size(size);
+                image
+            },
+            Err(err) => {
+                warn!("Failed to load icon {}: {err}", path.display());
+                Icon::themed(MISSING_ICON, size)
+            },
+        }
+    }
+
+    fn glyph(glyph: &str, size: i32) -> gtk::Label {
+        let attributes = pango::AttrList::new();
+        let font_size = (size as f64 * GLYPH_SCALE * pango::SCALE as f64) as i32;
+        attributes.insert(pango::AttrSize::new_size_absolute(font_size));
+
+        let label = gtk::Label::new(Some(glyph));
+        label.set_attributes(Some(&attributes));
+        label
+    }
 }
@@ -0,0 +1,118 @@
+use std::collections::HashSet;
+
+use gtk::prelude::*;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::event::Event;
+use crate::state::*;
+use crate::widgets::{FromWidgetConfig, HorizonWidget, WidgetContext};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LabelOptions {
+    /// The name of a `[[state]]`.
+    state: String,
+    /// Text with `{field}` placeholders filled from the state. Defaults to the whole value.
+    #[serde(default)]
+    format: Option<String>,
+}
+
+/// Shows the value of a user defined state as text.
+pub struct Label {
+    gtk_widget: gtk::Label,
+    state: String,
+    format: Option<String>,
+}
+
+impl FromWidgetConfig for Label {
+    const NAME: &'static str = "label";
+    type Options = LabelOptions;
+
+    fn validate(options: &LabelOptions, states: &HashSet<String>) -> Result<(), String> {
+        match states.contains(&options.state) {
+            true => Ok(()),
+            false => Err(format!("Label widget uses undefined state '{}'", options.state)),
+        }
+    }
+
+    fn new(context: &WidgetContext, options: LabelOptions) -> Self {
+        let label = gtk::Label::new(Some(&Label::formatted(&options.state, options.format.as_deref())));
+
+        Self {
+            gtk_widget: label,
+            state: options.state,
+            format: options.format,
+        }
+    }
+}
+
+impl HorizonWidget for Label {
+    fn widget(&self) -> gtk::Widget {
+        self.gtk_widget.clone().upcast()
+    }
+
+    fn topics(&self) -> Vec<Event> {
+        vec![Event::StateUpdated(self.state.clone())]
+    }
+
+    fn update(&self, _event: &Event) {
+        self.gtk_widget.set_label(&Label::formatted(&self.state, self.format.as_deref()));
+    }
+}
+
+impl Label {
+    fn formatted(state: &str, format: Option<&str>) -> String {
+        let Some(state) = CustomState::get(state) else {
+            return String::new();
+        };
+
+        let value = &state.read().unwrap().value;
+
+        match format {
+            Some(format) => format_value(format, value),
+            None => value_to_string(value),
+        }
+    }
+}
+
+/// Replaces `{key}` and `{key.nested.0}` placeholders in `format` with fields of `value`.
+/// `{}` is replaced by the whole value.
+pub fn format_value(format: &str, value: &Value) -> String {
+    let mut formatted = String::with_capacity(format.len());
+    let mut rest = format;
+
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+
+        formatted.push_str(&rest[..start]);
+
+        let path = &rest[start + 1..start + end];
+        let field = path
+            .split('.')
+            .filter(|key| !key.is_empty())
+            .try_fold(value, |value, key| match value {
+                Value::Array(values) => key.parse::<usize>().ok().and_then(|index| values.get(index)),
+                value => value.get(key),
+            });
+
+        if let Some(field) = field {
+            formatted.push_str(&value_to_string(field));
+        }
+
+        rest = &rest[start + end + 1..];
+    }
+
+    formatted.push_str(rest);
+    formatted
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
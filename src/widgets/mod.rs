@@ -1,15 +1,245 @@
 #![allow(unused_imports)]
 
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use serde::de::DeserializeOwned;
+use tracing::error;
+
+use crate::config::WidgetConfig;
+use crate::event::{EVENT_MANAGER, Event};
+use crate::horizon::HorizonWindowConfig;
+use crate::x::x::XSessionContext;
+
+mod action;
+mod calendar;
 mod clock;
+mod container;
 mod icon;
+mod label;
+mod next_event;
+mod taskbar;
+mod window_icon;
+mod window_title;
 mod workspaces;
 
 pub use crate::widgets::{
+    action::Action,
+    action::WidgetActions,
+    calendar::Calendar,
     clock::Clock,
+    container::Container,
+    container::Orientation,
     icon::Icon,
+    icon::IconSource,
+    label::Label,
+    next_event::NextEvent,
+    taskbar::Taskbar,
+    window_icon::retain_window_icons,
+    window_icon::window_icon,
+    window_title::TitleRewrite,
+    window_title::WindowTitle,
     workspaces::Workspaces,
 };
 
+/// What a widget may need to know about the window it is placed in.
+#[derive(Clone)]
+pub struct WidgetContext {
+    pub x_session: Rc<XSessionContext>,
+    /// The X display number of the screen the window is drawn on.
+    pub screen: usize,
+    /// The height of the window in pixels.
+    pub height: i32,
+}
+
+impl WidgetContext {
+    pub fn new(x_session: Rc<XSessionContext>, config: &HorizonWindowConfig) -> Self {
+        let height = config.geometry(&x_session.layout).height;
+
+        Self {
+            x_session,
+            screen: config.screen,
+            height,
+        }
+    }
+}
+
+impl PartialEq for WidgetContext {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.x_session, &other.x_session)
+            && self.screen == other.screen
+            && self.height == other.height
+    }
+}
+
+/// A widget placed in a window.
+///
+/// Widgets receive the events of [`HorizonWidget::topics`] through [`HorizonWidget::update`]
+/// for as long as they are mounted with [`MountedWidget::new`].
 pub trait HorizonWidget {
-    fn update();
+    /// The root GTK widget, added to the window or a parent container.
+    fn widget(&self) -> gtk::Widget;
+
+    /// The events this widget wants to be updated on.
+    fn topics(&self) -> Vec<Event> {
+        Vec::new()
+    }
+
+    fn update(&self, _event: &Event) {}
+
+    /// The popup toggled by [`Action::Popup`], if the widget has one.
+    fn popup(&self) -> Option<gtk::Popover> {
+        None
+    }
+
+    /// Switches to the alternate view of the widget on [`Action::Toggle`], if it has one.
+    fn toggle(&self) {}
+
+    /// Moves `step` items forward or backward on [`Action::Next`] and [`Action::Previous`], if the widget has items.
+    fn cycle(&self, _step: isize) {}
+
+    /// Actions used where the config does not set any.
+    fn default_actions(&self) -> WidgetActions {
+        WidgetActions::default()
+    }
+
+    /// Called once the widget is removed from its window.
+    fn teardown(&self) {}
+}
+
+/// Builds a [`HorizonWidget`] from a `[[window.widgets]]` table.
+pub trait FromWidgetConfig: HorizonWidget + Sized + 'static {
+    /// The `type` of the widget in the config.
+    const NAME: &'static str;
+
+    /// The other keys of the widget table.
+    type Options: DeserializeOwned;
+
+    /// Checks the options beyond what deserializing does. `states` are the names of the user defined states.
+    fn validate(_options: &Self::Options, _states: &HashSet<String>) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn new(context: &WidgetContext, options: Self::Options) -> Self;
+}
+
+/// Builds a widget from its config, see [`FromWidgetConfig::new`].
+type BuildWidget = fn(&WidgetContext, &WidgetConfig) -> Result<Box<dyn HorizonWidget>, String>;
+
+/// A widget type known to the config loader.
+struct WidgetKind {
+    name: &'static str,
+    validate: fn(&WidgetConfig, &HashSet<String>) -> Result<(), String>,
+    build: BuildWidget,
+}
+
+impl WidgetKind {
+    const fn of<W: FromWidgetConfig>() -> Self {
+        Self {
+            name: W::NAME,
+            validate: |config, states| W::validate(&options::<W>(config)?, states),
+            build: |context, config| Ok(Box::new(W::new(context, options::<W>(config)?))),
+        }
+    }
+}
+
+/// Every widget that can be used in the config.
+const WIDGET_KINDS: &[WidgetKind] = &[
+    WidgetKind::of::<Container>(),
+    WidgetKind::of::<Clock>(),
+    WidgetKind::of::<Icon>(),
+    WidgetKind::of::<Label>(),
+    WidgetKind::of::<NextEvent>(),
+    WidgetKind::of::<Taskbar>(),
+    WidgetKind::of::<WindowTitle>(),
+    WidgetKind::of::<Workspaces>(),
+];
+
+fn widget_kind(config: &WidgetConfig) -> Result<&'static WidgetKind, String> {
+    WIDGET_KINDS
+        .iter()
+        .find(|kind| kind.name == config.kind)
+        .ok_or_else(|| format!("Unknown widget type '{}'", config.kind))
+}
+
+fn options<W: FromWidgetConfig>(config: &WidgetConfig) -> Result<W::Options, String> {
+    toml::Value::Table(config.options.clone())
+        .try_into()
+        .map_err(|err| format!("Invalid {} widget: {}", W::NAME, err.message()))
+}
+
+/// Checks that `config` names a known widget type with valid options and actions.
+pub fn validate_widget(config: &WidgetConfig, states: &HashSet<String>) -> Result<(), String> {
+    (widget_kind(config)?.validate)(config, states)?;
+    config.actions.validate()
+}
+
+/// Builds the widget described by `config`.
+pub fn build_widget(context: &WidgetContext, config: &WidgetConfig) -> Result<Box<dyn HorizonWidget>, String> {
+    (widget_kind(config)?.build)(context, config)
+}
+
+/// A widget receiving the events it subscribed to until it is dropped, which tears it down.
+pub struct MountedWidget {
+    widget: Rc<dyn HorizonWidget>,
+    root: gtk::Widget,
+    listener: Option<usize>,
+}
+
+impl MountedWidget {
+    pub fn new(widget: Box<dyn HorizonWidget>, actions: &WidgetActions) -> Self {
+        let widget: Rc<dyn HorizonWidget> = Rc::from(widget);
+        let root = widget.widget();
+        let topics = widget.topics();
+
+        actions.or(&widget.default_actions()).attach(&root, &widget);
+
+        let listener = (!topics.is_empty()).then(|| {
+            let (listener, receiver) = EVENT_MANAGER.subscribe(&topics);
+            let weak = Rc::downgrade(&widget);
+
+            // Ends once the listener is unregistered on drop.
+            glib::spawn_future_local(async move {
+                while let Ok(event) = receiver.recv().await {
+                    match weak.upgrade() {
+                        Some(widget) => widget.update(&event),
+                        None => break,
+                    }
+                }
+            });
+
+            listener
+        });
+
+        Self {
+            widget,
+            root,
+            listener,
+        }
+    }
+
+    /// Builds and mounts the widget described by `config`, logging why if that is not possible.
+    pub fn build(context: &WidgetContext, config: &WidgetConfig) -> Option<Self> {
+        match build_widget(context, config) {
+            Ok(widget) => Some(Self::new(widget, &config.actions)),
+            Err(err) => {
+                error!("{err}");
+                None
+            },
+        }
+    }
+
+    pub fn root(&self) -> &gtk::Widget {
+        &self.root
+    }
+}
+
+impl Drop for MountedWidget {
+    fn drop(&mut self) {
+        if let Some(listener) = self.listener {
+            EVENT_MANAGER.unregister_listener(&listener);
+        }
+
+        self.widget.teardown();
+    }
 }
@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+
+use chrono::{Days, Local};
+use gtk::pango::EllipsizeMode;
+use gtk::prelude::*;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::event::Event;
+use crate::state::*;
+use crate::widgets::label::format_value;
+use crate::widgets::{FromWidgetConfig, HorizonWidget, WidgetContext};
+
+/// How far ahead the widget looks for the next event.
+const LOOKAHEAD_DAYS: u64 = 7;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NextEventOptions {
+    /// `.ics` files or vdir directories, see the `calendars` of the clock.
+    calendars: Vec<String>,
+    /// Text with `{time}`, `{summary}`, `{location}` and `{calendar}` placeholders.
+    #[serde(default = "default_format")]
+    format: String,
+    /// In characters, longer text is ellipsized.
+    #[serde(default)]
+    max_length: Option<usize>,
+    /// Shown while there is no upcoming event.
+    #[serde(default)]
+    fallback: String,
+}
+
+/// Shows the next event, or the current one, of local calendars.
+///
+/// All-day events are left out, they would take the place of the timed ones for the whole day.
+pub struct NextEvent {
+    gtk_widget: gtk::Label,
+    calendars: Vec<String>,
+    format: String,
+    fallback: String,
+}
+
+fn default_format() -> String {
+    String::from("{time} {summary}")
+}
+
+impl FromWidgetConfig for NextEvent {
+    const NAME: &'static str = "next_event";
+    type Options = NextEventOptions;
+
+    fn validate(options: &NextEventOptions, _states: &HashSet<String>) -> Result<(), String> {
+        if options.calendars.is_empty() {
+            return Err(String::from("Next event widget needs at least one calendar"));
+        }
+
+        match options.max_length {
+            Some(0) => Err(String::from("Next event widget must have a max_length of at least 1")),
+            _ => Ok(()),
+        }
+    }
+
+    fn new(context: &WidgetContext, options: NextEventOptions) -> Self {
+        AGENDA.write().unwrap().add_sources(&options.calendars);
+
+        let label = gtk::Label::new(None);
+        label.add_css_class("next-event");
+
+        if let Some(max_length) = options.max_length {
+            label.set_max_width_chars(max_length as i32);
+            label.set_ellipsize(EllipsizeMode::End);
+        }
+
+        let next_event = Self {
+            gtk_widget: label,
+            calendars: options.calendars,
+            format: options.format,
+            fallback: options.fallback,
+        };
+
+        next_event.update(&Event::HorizonAgendaUpdated);
+        next_event
+    }
+}
+
+impl HorizonWidget for NextEvent {
+    fn widget(&self) -> gtk::Widget {
+        self.gtk_widget.clone().upcast()
+    }
+
+    fn topics(&self) -> Vec<Event> {
+        // Date and time updates drop events that have ended, the agenda itself only refreshes every few minutes.
+        vec![Event::HorizonAgendaUpdated, Event::HorizonDateTimeUpdated]
+    }
+
+    fn update(&self, _event: &Event) {
+        let now = Local::now();
+        let until = local_midnight(now.date_naive() + Days::new(LOOKAHEAD_DAYS));
+        let agenda = AGENDA.read().unwrap();
+        let label = &self.gtk_widget;
+
+        let Some(event) = agenda.upcoming(now, until).find(|event| !event.all_day) else {
+            label.set_label(&self.fallback);
+            label.set_tooltip_text(None);
+            label.add_css_class("empty");
+            return;
+        };
+
+        let time = match event.start.date_naive() == now.date_naive() {
+            true => event.start.format("%H:%M"),
+            false => event.start.format("%a %H:%M"),
+        };
+
+        let value = json!({
+            "time": time.to_string(),
+            "summary": event.summary,
+            "location": event.location.clone().unwrap_or_default(),
+            "calendar": event.calendar,
+        });
+
+        let tooltip = format!(
+            "{}\n{} - {}{}",
+            event.summary,
+            event.start.format("%a, %b %-d %H:%M"),
+            event.end.format("%H:%M"),
+            event.location.as_ref().map(|location| format!("\n{location}")).unwrap_or_default(),
+        );
+
+        label.set_label(&format_value(&self.format, &value));
+        label.set_tooltip_text(Some(&tooltip));
+        label.remove_css_class("empty");
+
+        match event.start <= now {
+            true => label.add_css_class("ongoing"),
+            false => label.remove_css_class("ongoing"),
+        }
+    }
+
+    fn teardown(&self) {
+        AGENDA.write().unwrap().remove_sources(&self.calendars);
+    }
+}
@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use glib::clone;
+use gtk::prelude::*;
+use gtk::{Button, GestureClick};
+use serde::Deserialize;
+use tracing::warn;
+use x11rb::protocol::xproto::{Atom, Window};
+
+use crate::event::Event;
+use crate::state::*;
+use crate::widgets::{retain_window_icons, window_icon, FromWidgetConfig, HorizonWidget, WidgetContext};
+use crate::x::ewmh::{self, WindowType, SOURCE_PAGER};
+use crate::x::x::XSessionContext;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TaskbarOptions {
+    /// List the windows of every workspace instead of the current one.
+    #[serde(default)]
+    all_workspaces: bool,
+}
+
+/// A button per client window. Clicking a button activates the window, middle clicking closes it.
+///
+/// Docks, desktops and windows asking to be skipped are left out.
+pub struct Taskbar {
+    gtk_widget: gtk::Box,
+    x_session: Rc<XSessionContext>,
+    all_workspaces: bool,
+    icon_size: i32,
+    /// The button of each listed window, updated in place as the window changes.
+    tasks: RefCell<HashMap<Window, Task>>,
+}
+
+/// The button of a window, along with the state of the window it currently shows.
+struct Task {
+    button: Button,
+    content: gtk::Box,
+    icon: gtk::Image,
+    title: gtk::Label,
+    window: HorizonWmWindow,
+}
+
+impl FromWidgetConfig for Taskbar {
+    const NAME: &'static str = "taskbar";
+    type Options = TaskbarOptions;
+
+    fn new(context: &WidgetContext, options: TaskbarOptions) -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        container.add_css_class("taskbar");
+
+        let taskbar = Self {
+            gtk_widget: container,
+            x_session: context.x_session.clone(),
+            all_workspaces: options.all_workspaces,
+            icon_size: context.height,
+            tasks: RefCell::new(HashMap::new()),
+        };
+
+        taskbar.sync();
+        taskbar
+    }
+}
+
+impl HorizonWidget for Taskbar {
+    fn widget(&self) -> gtk::Widget {
+        self.gtk_widget.clone().upcast()
+    }
+
+    fn topics(&self) -> Vec<Event> {
+        vec![Event::HorizonWmUpdated]
+    }
+
+    fn update(&self, _event: &Event) {
+        self.sync();
+    }
+}
+
+impl Taskbar {
+    /// Adds, updates, removes and reorders the buttons to match the windows in [`WM`].
+    fn sync(&self) {
+        let wm = WM.read().unwrap();
+        let mut shown = HashSet::new();
+
+        retain_window_icons(wm.window_ids());
+
+        let windows = wm.workspaces
+            .iter()
+            .filter(|workspace| self.all_workspaces || workspace.visible)
+            .flat_map(|workspace| workspace.windows.iter())
+            .filter(|window| !window.skip_taskbar && !matches!(window.window_type, WindowType::Dock | WindowType::Desktop))
+            // Sticky windows are on every workspace, only show them once.
+            .filter(|window| shown.insert(window.id))
+            .collect::<Vec<_>>();
+
+        let mut tasks = self.tasks.borrow_mut();
+
+        tasks.retain(|id, task| {
+            let keep = shown.contains(id);
+            if !keep {
+                self.gtk_widget.remove(&task.button);
+            }
+            keep
+        });
+
+        let mut previous: Option<Button> = None;
+
+        for window in windows {
+            let button = match tasks.get_mut(&window.id) {
+                Some(task) => {
+                    task.update(window, self.icon_size);
+                    self.gtk_widget.reorder_child_after(&task.button, previous.as_ref());
+                    task.button.clone()
+                },
+                None => {
+                    let task = Task::new(window, &self.x_session, self.icon_size);
+                    self.gtk_widget.insert_child_after(&task.button, previous.as_ref());
+                    let button = task.button.clone();
+                    tasks.insert(window.id, task);
+                    button
+                },
+            };
+
+            previous = Some(button);
+        }
+    }
+}
+
+impl Task {
+    fn new(window: &HorizonWmWindow, x_session: &Rc<XSessionContext>, icon_size: i32) -> Self {
+        let content = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+
+        let icon = window_icon(window, icon_size);
+        content.append(&icon);
+
+        let title = gtk::Label::new(Some(&window.name));
+        title.set_max_width_chars(20);
+        title.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        content.append(&title);
+
+        let button = Button::builder()
+            .child(&content)
+            .tooltip_text(window.name.as_str())
+            .build();
+        button.add_css_class("task");
+
+        let id = window.id;
+
+        button.connect_clicked(clone!(@strong x_session => move |_| {
+            let atom = x_session.atoms._NET_ACTIVE_WINDOW;
+            send_to_window(&x_session, id, atom, [SOURCE_PAGER, x11rb::CURRENT_TIME, 0, 0, 0]);
+        }));
+
+        let middle_click = GestureClick::new();
+        middle_click.set_button(gtk::gdk::BUTTON_MIDDLE);
+        middle_click.connect_pressed(clone!(@strong x_session => move |_, _, _, _| {
+            let atom = x_session.atoms._NET_CLOSE_WINDOW;
+            send_to_window(&x_session, id, atom, [x11rb::CURRENT_TIME, SOURCE_PAGER, 0, 0, 0]);
+        }));
+        button.add_controller(middle_click);
+
+        let task = Self {
+            button,
+            content,
+            icon,
+            title,
+            window: window.clone(),
+        };
+
+        task.set_classes();
+        task
+    }
+
+    /// Only touches the parts of the button showing something that changed.
+    fn update(&mut self, window: &HorizonWmWindow, icon_size: i32) {
+        if self.window.name != window.name {
+            self.title.set_label(&window.name);
+            self.button.set_tooltip_text(Some(&window.name));
+        }
+
+        if !Arc::ptr_eq(&self.window.icons, &window.icons) || self.window.class != window.class {
+            let icon = window_icon(window, icon_size);
+            self.content.remove(&self.icon);
+            self.content.prepend(&icon);
+            self.icon = icon;
+        }
+
+        self.window = window.clone();
+        self.set_classes();
+    }
+
+    fn set_classes(&self) {
+        for (class, set) in [
+            ("focused", self.window.focused),
+            ("minimized", self.window.minimized),
+            ("fullscreen", self.window.fullscreened),
+            ("urgent", self.window.urgent_flag_set),
+        ] {
+            match set {
+                true => self.button.add_css_class(class),
+                false => self.button.remove_css_class(class),
+            }
+        }
+    }
+}
+
+fn send_to_window(x_session: &XSessionContext, window: Window, message_type: Atom, data: [u32; 5]) {
+    let result = ewmh::send_client_message(x_session.connection.as_ref(), x_session.root, window, message_type, data);
+
+    if let Err(err) = result {
+        warn!("Failed to send client message to window {window}: {err}");
+    }
+}
@@ -0,0 +1,104 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use gtk::gdk;
+use gtk::prelude::*;
+use x11rb::protocol::xproto::Window;
+
+use crate::state::HorizonWmWindow;
+use crate::x::ewmh::WmIcon;
+
+/// Shown for windows that have neither a `_NET_WM_ICON` nor a themed icon for their WM_CLASS.
+const FALLBACK_ICON: &str = "application-x-executable";
+
+struct CachedIcon {
+    /// The icons the texture was made from, to notice when the window changes its icon.
+    icons: Arc<[WmIcon]>,
+    size: i32,
+    texture: gdk::Texture,
+}
+
+thread_local! {
+    /// Textures are only usable on the GTK thread, so the cache lives there.
+    static ICON_CACHE: RefCell<HashMap<Window, CachedIcon>> = RefCell::new(HashMap::new());
+}
+
+/// An image of the icon of `window`, `size` pixels high.
+///
+/// Uses the `_NET_WM_ICON` of the window closest to `size`, or an icon theme lookup by WM_CLASS.
+pub fn window_icon(window: &HorizonWmWindow, size: i32) -> gtk::Image {
+    let image = match window_texture(window, size) {
+        Some(texture) => gtk::Image::from_paintable(Some(&texture)),
+        None => gtk::Image::from_icon_name(&themed_icon_name(window)),
+    };
+
+    image.set_pixel_size(size);
+    image
+}
+
+/// Drops the cached icons of windows that are not in `windows` anymore.
+pub fn retain_window_icons(windows: &HashSet<Window>) {
+    ICON_CACHE.with_borrow_mut(|cache| cache.retain(|window, _| windows.contains(window)));
+}
+
+fn window_texture(window: &HorizonWmWindow, size: i32) -> Option<gdk::Texture> {
+    ICON_CACHE.with_borrow_mut(|cache| {
+        if let Some(cached) = cache.get(&window.id) {
+            if cached.size == size && Arc::ptr_eq(&cached.icons, &window.icons) {
+                return Some(cached.texture.clone());
+            }
+        }
+
+        let texture = to_texture(best_icon(&window.icons, size)?);
+        cache.insert(window.id, CachedIcon {
+            icons: window.icons.clone(),
+            size,
+            texture: texture.clone(),
+        });
+
+        Some(texture)
+    })
+}
+
+/// The smallest icon at least `size` pixels high, or the largest one if all of them are smaller.
+fn best_icon(icons: &[WmIcon], size: i32) -> Option<&WmIcon> {
+    let size = size.max(1) as u32;
+
+    icons.iter()
+        .filter(|icon| icon.height >= size)
+        .min_by_key(|icon| icon.height)
+        .or_else(|| icons.iter().max_by_key(|icon| icon.height))
+}
+
+fn to_texture(icon: &WmIcon) -> gdk::Texture {
+    let bytes = icon.pixels
+        .iter()
+        .flat_map(|argb| {
+            let [a, r, g, b] = argb.to_be_bytes();
+            [r, g, b, a]
+        })
+        .collect::<Vec<u8>>();
+
+    gdk::MemoryTexture::new(
+        icon.width as i32,
+        icon.height as i32,
+        gdk::MemoryFormat::R8g8b8a8,
+        &glib::Bytes::from_owned(bytes),
+        icon.width as usize * 4,
+    ).upcast()
+}
+
+/// Tries the instance and class part of WM_CLASS against the icon theme.
+fn themed_icon_name(window: &HorizonWmWindow) -> String {
+    let Some(display) = gdk::Display::default() else {
+        return String::from(FALLBACK_ICON);
+    };
+
+    let theme = gtk::IconTheme::for_display(&display);
+
+    [window.class.0.to_lowercase(), window.icon.clone()]
+        .into_iter()
+        .find(|name| !name.is_empty() && theme.has_icon(name))
+        .unwrap_or_else(|| String::from(FALLBACK_ICON))
+}
@@ -0,0 +1,114 @@
+use std::collections::HashSet;
+
+use gtk::pango::EllipsizeMode;
+use gtk::prelude::*;
+use serde::Deserialize;
+
+use crate::event::Event;
+use crate::state::*;
+use crate::widgets::{FromWidgetConfig, HorizonWidget, WidgetContext};
+
+/// Replaces the title of windows of a given WM_CLASS.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TitleRewrite {
+    /// Matches either the instance or the class part of WM_CLASS, e.g. `firefox`.
+    pub class: String,
+    /// The text to show instead, `{title}` and `{class}` are replaced with the window's values.
+    pub title: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WindowTitleOptions {
+    /// In characters, longer titles are ellipsized.
+    #[serde(default)]
+    max_length: Option<usize>,
+    #[serde(default)]
+    rewrite: Vec<TitleRewrite>,
+    /// Shown while no window is focused.
+    #[serde(default)]
+    fallback: String,
+}
+
+/// Shows the title of the focused window.
+pub struct WindowTitle {
+    gtk_widget: gtk::Label,
+    rewrites: Vec<TitleRewrite>,
+    fallback: String,
+}
+
+impl FromWidgetConfig for WindowTitle {
+    const NAME: &'static str = "window_title";
+    type Options = WindowTitleOptions;
+
+    fn validate(options: &WindowTitleOptions, _states: &HashSet<String>) -> Result<(), String> {
+        match options.max_length {
+            Some(0) => Err(String::from("Window title widget must have a max_length of at least 1")),
+            _ => Ok(()),
+        }
+    }
+
+    fn new(context: &WidgetContext, options: WindowTitleOptions) -> Self {
+        let label = gtk::Label::new(None);
+        label.add_css_class("window-title");
+
+        if let Some(max_length) = options.max_length {
+            label.set_max_width_chars(max_length as i32);
+            label.set_ellipsize(EllipsizeMode::End);
+        }
+
+        let title = Self {
+            gtk_widget: label,
+            rewrites: options.rewrite,
+            fallback: options.fallback,
+        };
+
+        title.update(&Event::HorizonWmUpdated);
+        title
+    }
+}
+
+impl HorizonWidget for WindowTitle {
+    fn widget(&self) -> gtk::Widget {
+        self.gtk_widget.clone().upcast()
+    }
+
+    fn topics(&self) -> Vec<Event> {
+        vec![Event::HorizonWmUpdated]
+    }
+
+    fn update(&self, _event: &Event) {
+        let wm = WM.read().unwrap();
+        let label = &self.gtk_widget;
+
+        match wm.focused_window() {
+            Some(window) => {
+                label.set_label(&rewrite_title(window, &self.rewrites));
+                label.set_tooltip_text(Some(&window.name));
+                label.remove_css_class("empty");
+            },
+            None => {
+                label.set_label(&self.fallback);
+                label.set_tooltip_text(None);
+                label.add_css_class("empty");
+            },
+        }
+    }
+}
+
+/// The title of `window` after applying the first matching rule in `rewrites`.
+fn rewrite_title(window: &HorizonWmWindow, rewrites: &[TitleRewrite]) -> String {
+    let (instance, class) = &window.class;
+
+    let rule = rewrites
+        .iter()
+        .find(|rule| rule.class.eq_ignore_ascii_case(instance) || rule.class.eq_ignore_ascii_case(class));
+
+    match rule {
+        Some(rule) => rule.title
+            .replace("{title}", &window.name)
+            .replace("{class}", class),
+        None => window.name.clone(),
+    }
+}
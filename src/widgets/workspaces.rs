@@ -1,5 +1,178 @@
-use gtk::Widget;
+use std::rc::Rc;
 
+use glib::{clone, Propagation};
+use gtk::prelude::*;
+use gtk::{Button, EventControllerScroll, EventControllerScrollFlags};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::event::Event;
+use crate::state::*;
+use crate::widgets::{FromWidgetConfig, HorizonWidget, WidgetContext};
+use crate::x::ewmh;
+use crate::x::x::XSessionContext;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspacesOptions {
+    /// Only show the workspaces on the monitor of the window.
+    #[serde(default)]
+    monitor_only: bool,
+}
+
+/// A button per workspace of the window manager. Clicking a button switches to it,
+/// scrolling over the widget cycles through the shown workspaces.
 pub struct Workspaces {
-    gtk_widget: Widget,
+    gtk_widget: gtk::Box,
+    x_session: Rc<XSessionContext>,
+    monitor: Option<Monitor>,
+}
+
+/// The monitor of the window, set with `monitor_only`.
+#[derive(Debug, Clone, Copy)]
+struct Monitor {
+    index: usize,
+    /// (start_x, end_x, start_y, end_y)
+    bounds: (i32, i32, i32, i32),
+}
+
+impl FromWidgetConfig for Workspaces {
+    const NAME: &'static str = "workspaces";
+    type Options = WorkspacesOptions;
+
+    fn new(context: &WidgetContext, options: WorkspacesOptions) -> Self {
+        let container = gtk::Box::new(gtk::Orientation::Horizontal, 0);
+        container.add_css_class("workspaces");
+
+        let x_session = context.x_session.clone();
+        let monitor = options.monitor_only.then(|| Monitor {
+            index: context.screen,
+            bounds: x_session.layout.get_monitor_bounds(context.screen),
+        });
+
+        Workspaces::rebuild(&container, &x_session, monitor);
+
+        let scroll = EventControllerScroll::new(EventControllerScrollFlags::VERTICAL | EventControllerScrollFlags::DISCRETE);
+        scroll.connect_scroll(clone!(@strong x_session => move |_, _, dy| {
+            let step = if dy > 0.0 { 1 } else { -1 };
+            Workspaces::cycle(&x_session, monitor, step);
+            Propagation::Stop
+        }));
+        container.add_controller(scroll);
+
+        Self {
+            gtk_widget: container,
+            x_session,
+            monitor,
+        }
+    }
+}
+
+impl HorizonWidget for Workspaces {
+    fn widget(&self) -> gtk::Widget {
+        self.gtk_widget.clone().upcast()
+    }
+
+    fn topics(&self) -> Vec<Event> {
+        vec![Event::HorizonWmUpdated]
+    }
+
+    fn update(&self, _event: &Event) {
+        Workspaces::rebuild(&self.gtk_widget, &self.x_session, self.monitor);
+    }
+}
+
+impl Workspaces {
+    /// Replaces the buttons with one per shown workspace of [`WM`].
+    fn rebuild(container: &gtk::Box, x_session: &Rc<XSessionContext>, monitor: Option<Monitor>) {
+        while let Some(child) = container.first_child() {
+            container.remove(&child);
+        }
+
+        let wm = WM.read().unwrap();
+
+        for workspace in wm.workspaces.iter().filter(|workspace| on_monitor(workspace, &wm.workspaces, monitor)) {
+            let label = match workspace.icon.is_empty() {
+                true => workspace.name.clone(),
+                false => format!("{} {}", workspace.icon, workspace.name),
+            };
+
+            let button = Button::with_label(&label);
+            button.add_css_class("workspace");
+
+            // NOTE: EWMH only knows a single current desktop, so focused and visible are the same for now.
+            let urgent = workspace.windows.iter().any(|window| window.urgent_flag_set);
+
+            for (class, set) in [
+                ("focused", workspace.visible),
+                ("visible", workspace.visible),
+                ("urgent", urgent),
+                ("empty", workspace.windows.is_empty()),
+                ("occupied", !workspace.windows.is_empty()),
+            ] {
+                if set {
+                    button.add_css_class(class);
+                }
+            }
+
+            let number = workspace.number;
+            button.connect_clicked(clone!(@strong x_session => move |_| {
+                switch_to(&x_session, number);
+            }));
+
+            container.append(&button);
+        }
+    }
+
+    /// Switches `step` workspaces forward or backward, wrapping around.
+    fn cycle(x_session: &XSessionContext, monitor: Option<Monitor>, step: isize) {
+        let wm = WM.read().unwrap();
+        let numbers = wm.workspaces
+            .iter()
+            .filter(|workspace| on_monitor(workspace, &wm.workspaces, monitor))
+            .map(|workspace| (workspace.number, workspace.visible))
+            .collect::<Vec<_>>();
+
+        let Some(current) = numbers.iter().position(|(_, visible)| *visible) else {
+            return;
+        };
+
+        let next = (current as isize + step).rem_euclid(numbers.len() as isize) as usize;
+        switch_to(x_session, numbers[next].0);
+    }
+}
+
+/// Whether the workspace is on `monitor`, from its screen or else its viewport among all `workspaces`.
+/// Workspaces on an unknown monitor, and all workspaces without a monitor, are treated as being on it.
+fn on_monitor(workspace: &HorizonWmWorkspace, workspaces: &[HorizonWmWorkspace], monitor: Option<Monitor>) -> bool {
+    let Some(monitor) = monitor else {
+        return true;
+    };
+
+    if let Some(screen) = workspace.screen {
+        return screen as usize == monitor.index;
+    }
+
+    // Most WMs report the same viewport, usually (0, 0), for every desktop, which says nothing about the monitor.
+    let viewports_differ = workspaces.iter().any(|other| other.viewport != workspace.viewport);
+    let (start_x, end_x, start_y, end_y) = monitor.bounds;
+
+    match workspace.viewport {
+        Some((x, y)) if viewports_differ => (start_x..end_x).contains(&x) && (start_y..end_y).contains(&y),
+        _ => true,
+    }
+}
+
+fn switch_to(x_session: &XSessionContext, number: u32) {
+    let result = ewmh::send_client_message(
+        x_session.connection.as_ref(),
+        x_session.root,
+        x_session.root,
+        x_session.atoms._NET_CURRENT_DESKTOP,
+        [number, 0, 0, 0, 0],
+    );
+
+    if let Err(err) = result {
+        warn!("Failed to switch to workspace {number}: {err}");
+    }
 }
@@ -1,4 +1,8 @@
-use x11rb::{atom_manager, protocol::xproto::Atom};
+use serde::Deserialize;
+use x11rb::connection::Connection;
+use x11rb::errors::{ConnectionError, ReplyError};
+use x11rb::protocol::xproto::{Atom, AtomEnum, ClientMessageEvent, ConnectionExt, EventMask, Window};
+use x11rb::atom_manager;
 
 use super::strut::StrutPartialDef;
 
@@ -23,22 +27,43 @@ atom_manager! {
         _NET_WM_STATE_MAXIMIZED_VERT,
         _NET_WM_STATE_MAXIMIZED_HORZ,
         _NET_WM_STATE_MODAL,
+        _NET_WM_STRUT,
         _NET_WM_STRUT_PARTIAL,
         _NET_WM_STATE_SHADED,
         _NET_WM_STATE_SKIP_TASKBAR,
         _NET_WM_STATE_SKIP_PAGER,
         _NET_WM_STATE_STICKY,
         _NET_WM_NAME,
+        _NET_WM_ICON,
+        _NET_WM_DESKTOP,
+        _NET_NUMBER_OF_DESKTOPS,
+        _NET_DESKTOP_NAMES,
+        _NET_CURRENT_DESKTOP,
+        _NET_DESKTOP_VIEWPORT,
+        _NET_CLIENT_LIST,
+        _NET_ACTIVE_WINDOW,
+        _NET_CLOSE_WINDOW,
+        UTF8_STRING,
         ATOM,
+        _HORIZON_WINDOW,
     }
 }
 
+/// `_NET_WM_DESKTOP` value of windows shown on every desktop.
+pub const ALL_DESKTOPS: u32 = 0xFFFFFFFF;
+/// Source indication of client messages sent on behalf of the user, such as by pagers and taskbars.
+pub const SOURCE_PAGER: u32 = 2;
+/// The `UrgencyHint` flag of the ICCCM `WM_HINTS` property.
+pub const WM_HINTS_URGENCY: u32 = 1 << 8;
+
 
 /// Possible types for `_NET_WM_WINDOW_TYPE`.
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum WindowType {
     Desktop,
     Dialog,
+    #[default]
     Dock,
     Menu,
     Notification,  // NOTE: Does not appear in the ewmh spec
@@ -48,8 +73,10 @@ pub enum WindowType {
     Utility,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum WindowAnchor {
+    #[default]
     TopLeft,
     TopCenter,
     TopRight,
@@ -61,12 +88,32 @@ pub enum WindowAnchor {
     BottomRight,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum WindowStackPosition {
     Foreground,
+    #[default]
     Background,
 }
 
+impl WindowType {
+    /// The first type of a `_NET_WM_WINDOW_TYPE` list horizon knows, as the list is in order of preference.
+    pub fn from_atoms(atoms: &AtomCollection, types: &[Atom]) -> Option<Self> {
+        types.iter().find_map(|atom| match *atom {
+            atom if atom == atoms._NET_WM_WINDOW_TYPE_DESKTOP => Some(WindowType::Desktop),
+            atom if atom == atoms._NET_WM_WINDOW_TYPE_DOCK => Some(WindowType::Dock),
+            atom if atom == atoms._NET_WM_WINDOW_TYPE_DIALOG => Some(WindowType::Dialog),
+            atom if atom == atoms._NET_WM_WINDOW_TYPE_MENU => Some(WindowType::Menu),
+            atom if atom == atoms._NET_WM_WINDOW_TYPE_NORMAL => Some(WindowType::Normal),
+            atom if atom == atoms._NET_WM_WINDOW_TYPE_NOTIFICATION => Some(WindowType::Notification),
+            atom if atom == atoms._NET_WM_WINDOW_TYPE_SPLASH => Some(WindowType::Splash),
+            atom if atom == atoms._NET_WM_WINDOW_TYPE_TOOLBAR => Some(WindowType::Toolbar),
+            atom if atom == atoms._NET_WM_WINDOW_TYPE_UTILITY => Some(WindowType::Utility),
+            _ => None,
+        })
+    }
+}
+
 pub struct EwmhHints {
     pub anchor: Atom,
     pub stack_positiona: Atom,
@@ -100,3 +147,111 @@ impl EwmhHints {
         }
     }
 }
+
+/// Reads a property made of 32 bit values, such as CARDINAL, WINDOW or ATOM lists.
+///
+/// A missing property is returned as an empty list.
+pub fn get_u32_list(connection: &impl Connection, window: Window, property: Atom) -> Result<Vec<u32>, ReplyError> {
+    let reply = connection
+        .get_property(false, window, property, AtomEnum::ANY, 0, u32::MAX)?
+        .reply()?;
+
+    Ok(reply.value32().map(Iterator::collect).unwrap_or_default())
+}
+
+/// Reads the first value of a 32 bit property.
+pub fn get_u32(connection: &impl Connection, window: Window, property: Atom) -> Result<Option<u32>, ReplyError> {
+    Ok(get_u32_list(connection, window, property)?.first().copied())
+}
+
+/// Reads the space a client reserves at the edges of the screen, from `_NET_WM_STRUT_PARTIAL` or else `_NET_WM_STRUT`.
+pub fn get_strut(connection: &impl Connection, atoms: &AtomCollection, window: Window) -> Result<Option<StrutPartialDef>, ReplyError> {
+    let partial = get_u32_list(connection, window, atoms._NET_WM_STRUT_PARTIAL)?;
+
+    if !partial.is_empty() {
+        return Ok(StrutPartialDef::from_values(&partial));
+    }
+
+    Ok(StrutPartialDef::from_values(&get_u32_list(connection, window, atoms._NET_WM_STRUT)?))
+}
+
+/// Reads a property made of NUL separated strings, such as `_NET_DESKTOP_NAMES` or `WM_CLASS`.
+pub fn get_strings(connection: &impl Connection, window: Window, property: Atom) -> Result<Vec<String>, ReplyError> {
+    let reply = connection
+        .get_property(false, window, property, AtomEnum::ANY, 0, u32::MAX)?
+        .reply()?;
+
+    let value = reply.value.strip_suffix(&[0]).unwrap_or(&reply.value);
+    if value.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    Ok(value
+        .split(|byte| *byte == 0)
+        .map(|string| String::from_utf8_lossy(string).into_owned())
+        .collect())
+}
+
+/// A single image of the `_NET_WM_ICON` property of a client.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WmIcon {
+    pub width: u32,
+    pub height: u32,
+    /// Non-premultiplied ARGB, one value per pixel, row by row.
+    pub pixels: Vec<u32>,
+}
+
+/// Reads every image of `_NET_WM_ICON`, which holds a width, a height and then the pixels for each size.
+///
+/// Truncated images at the end of the property are dropped.
+pub fn get_wm_icons(connection: &impl Connection, atoms: &AtomCollection, window: Window) -> Result<Vec<WmIcon>, ReplyError> {
+    let data = get_u32_list(connection, window, atoms._NET_WM_ICON)?;
+    let mut icons = Vec::new();
+    let mut rest = data.as_slice();
+
+    while let [width, height, pixels @ ..] = rest {
+        let length = (*width as usize).saturating_mul(*height as usize);
+        if length == 0 || length > pixels.len() {
+            break;
+        }
+
+        icons.push(WmIcon {
+            width: *width,
+            height: *height,
+            pixels: pixels[..length].to_vec(),
+        });
+
+        rest = &pixels[length..];
+    }
+
+    Ok(icons)
+}
+
+/// Reads the title of a client from `_NET_WM_NAME`, falling back to the ICCCM `WM_NAME`.
+pub fn get_window_name(connection: &impl Connection, atoms: &AtomCollection, window: Window) -> Result<String, ReplyError> {
+    let name = get_strings(connection, window, atoms._NET_WM_NAME)?.into_iter().next();
+
+    match name {
+        Some(name) => Ok(name),
+        None => Ok(get_strings(connection, window, AtomEnum::WM_NAME.into())?
+            .into_iter()
+            .next()
+            .unwrap_or_default()),
+    }
+}
+
+/// Sends an EWMH client message to the window manager through the root window,
+/// e.g. `_NET_CURRENT_DESKTOP` to switch workspaces.
+pub fn send_client_message(
+    connection: &impl Connection,
+    root: Window,
+    window: Window,
+    message_type: Atom,
+    data: [u32; 5],
+) -> Result<(), ConnectionError> {
+    let event = ClientMessageEvent::new(32, window, message_type, data);
+    let mask = EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT;
+
+    connection.send_event(false, root, mask, event)?;
+    connection.flush()
+}
@@ -1,3 +1,4 @@
 pub mod ewmh;
 pub mod strut;
+pub mod watch;
 pub mod x;
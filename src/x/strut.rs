@@ -1,9 +1,12 @@
-use std::{fmt, rc::Rc};
+use std::fmt;
+
+use serde::Deserialize;
+use thiserror::Error;
 
 use crate::util::*;
-use crate::x::x::XSessionContext;
+use crate::x::x::MonitorLayout;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
 pub struct StrutPartialDef {
     left: i32,
     right: i32,
@@ -17,6 +20,9 @@ pub struct StrutPartialDef {
     top_end_x: i32,
     bottom_start_x: i32,
     bottom_end_x: i32,
+    /// How far the strut, and so its window, was moved inward to make room for existing struts.
+    /// Not part of the X property.
+    offset: i32,
 }
 
 impl StrutPartialDef {
@@ -24,6 +30,51 @@ impl StrutPartialDef {
         StrutPartialDefBuilder::new()
     }
 
+    /// Reads the 12 values of `_NET_WM_STRUT_PARTIAL`, or the 4 of the older `_NET_WM_STRUT`,
+    /// which always spans the full edges. An all zero strut is `None`.
+    pub fn from_values(values: &[u32]) -> Option<Self> {
+        let values = values.iter().map(|value| *value as i32).collect::<Vec<_>>();
+
+        let strut = match values[..] {
+            [left, right, top, bottom, left_start_y, left_end_y, right_start_y, right_end_y, top_start_x, top_end_x, bottom_start_x, bottom_end_x] => Self {
+                left, right, top, bottom,
+                left_start_y, left_end_y,
+                right_start_y, right_end_y,
+                top_start_x, top_end_x,
+                bottom_start_x, bottom_end_x,
+                offset: 0,
+            },
+            [left, right, top, bottom] => Self {
+                left, right, top, bottom,
+                left_end_y: i32::MAX,
+                right_end_y: i32::MAX,
+                top_end_x: i32::MAX,
+                bottom_end_x: i32::MAX,
+                ..Default::default()
+            },
+            _ => return None,
+        };
+
+        match [strut.left, strut.right, strut.top, strut.bottom].iter().any(|thickness| *thickness > 0) {
+            true => Some(strut),
+            false => None,
+        }
+    }
+
+    pub fn offset(&self) -> i32 {
+        self.offset
+    }
+
+    /// The thickness and the inclusive start and end along `side`, all relative to the root window.
+    fn edge(&self, side: Side) -> (i32, i32, i32) {
+        match side {
+            Side::Left => (self.left, self.left_start_y, self.left_end_y),
+            Side::Right => (self.right, self.right_start_y, self.right_end_y),
+            Side::Top => (self.top, self.top_start_x, self.top_end_x),
+            Side::Bottom => (self.bottom, self.bottom_start_x, self.bottom_end_x),
+        }
+    }
+
     pub fn as_x11_ready_value(&self) -> Vec<u8> {
         self.as_vec()
             .iter()
@@ -68,110 +119,205 @@ impl fmt::Display for StrutPartialDef {
     }
 }
 
+/// User defined strut, resolved into a [`StrutPartialDef`] once the monitor geometry is known.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StrutConfig {
+    /// The edge of the monitor to reserve space on.
+    pub side: Side,
+    /// The thickness of the reserved space, percentages are of the monitor across the edge.
+    pub size: Length,
+    /// The start and end of the reserved space along the edge. Omit to reserve the full edge.
+    #[serde(default)]
+    pub span: Option<(Length, Length)>,
+}
+
+impl StrutConfig {
+    /// Resolves the strut on `monitor`, stacked against the `existing` struts of other docks on the same edge.
+    pub fn build(&self, layout: &MonitorLayout, monitor: usize, existing: &[StrutPartialDef]) -> Result<StrutPartialDef, StrutError> {
+        let builder = StrutPartialDef::builder()
+            .layout(layout.clone())
+            .monitor(monitor)
+            .existing_struts(existing.to_vec())
+            .side(self.side)
+            .size(self.size);
+
+        match self.span {
+            Some((start, end)) => builder.partial_length(start, end).build(),
+            None => builder.full_length().build(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum StrutError {
+    #[error("No monitor layout provided, so the monitor geometry is unknown")]
+    NoLayout,
+    #[error("Monitor {0} does not exist")]
+    NoMonitor(usize),
+    #[error("{0:?} strut must have a positive size, got {1}")]
+    InvalidSize(Side, Length),
+    #[error("{side:?} strut spans {start}..{end}, which is not within 0..{length}")]
+    InvalidSpan {
+        side: Side,
+        start: i32,
+        end: i32,
+        length: i32,
+    },
+}
+
+/// How much of an edge a strut covers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StrutSpan {
+    Full,
+    /// Start and end along the edge, relative to the monitor.
+    Partial(Length, Length),
+}
+
+/// An edge added through [`StrutPartialDefBuilder::side`], resolved against the monitor in `build`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct StrutEdge {
+    side: Side,
+    size: Length,
+    span: StrutSpan,
+}
+
+/// Builds a [`StrutPartialDef`] relative to a monitor.
+///
+/// Each edge goes through `side`, then `size`, then `full_length` or `partial_length`,
+/// so an edge can not be reserved without a thickness and a span.
 #[derive(Clone, Default)]
 pub struct StrutPartialDefBuilder {
-    x_session: Option<Rc<XSessionContext>>,
+    layout: Option<MonitorLayout>,
     monitor: usize,
     should_apply_offsets: bool,
-    // TODO: Account for existing struts when making new ones.
-    existing_struts: Option<Vec<StrutPartialDef>>,
-    // Does not track if size is set. That is handles by checking if size is 0.
-    non_zero_value_set: bool,
-    size: i32,
-    left: i32,
-    right: i32,
-    top: i32,
-    bottom: i32,
-    left_start_y: i32,
-    left_end_y: i32,
-    right_start_y: i32,
-    right_end_y: i32,
-    top_start_x: i32,
-    top_end_x: i32,
-    bottom_start_x: i32,
-    bottom_end_x: i32,
+    /// Struts of other docks, which new struts are stacked against.
+    existing_struts: Vec<StrutPartialDef>,
+    edges: Vec<StrutEdge>,
+}
+
+/// The stage of [`StrutPartialDefBuilder`] waiting for the thickness of an edge.
+pub struct StrutPartialDefBuilderIntSize {
+    side: Side,
+    prev_builder: StrutPartialDefBuilder,
+}
+
+/// The stage of [`StrutPartialDefBuilder`] waiting for the span of an edge.
+pub struct StrutPartialDefBuilderIntLength {
+    side: Side,
+    size: Length,
+    prev_builder: StrutPartialDefBuilder,
 }
 
 impl StrutPartialDefBuilder {
     fn new() -> Self {
         Self {
-            x_session: None,
+            layout: None,
             should_apply_offsets: true,
-            existing_struts: None,
+            existing_struts: Vec::new(),
             ..Default::default()
         }
     }
 
-    pub fn build(mut self) -> StrutPartialDef {
-        // No strut coordinates implies a zero strut.
-        // if !self.non_zero_value_set {
-        //     return StrutPartialDef::default();
-        // }
-
-        // Unable to calculate offsets without an XSessionContext
-        // match self.x_session.is_none() && self.should_apply_offsets {
-        //     true => panic!("No XSessionContext provided, so offsets can't be applied."),
-        //     false => (),
-        // }
-
-        // Specifying strut coordinates without a size implies a mistake.
-        // Zero struts should be all zero.
-        // match self.non_zero_value_set && self.size == 0 {
-        //     true => panic!("A non-zero strut was specified, but no size was specified."),
-        //     false => (),
-        // }
-
-        if self.should_apply_offsets {
-            self.apply_offsets();
-        }
+    /// Resolves the edges against the monitor geometry. Without any edge, the strut is all zero.
+    pub fn build(self) -> Result<StrutPartialDef, StrutError> {
+        let mut strut = StrutPartialDef::default();
 
-        StrutPartialDef {
-            left: self.left,
-            right: self.right,
-            top: self.top,
-            bottom: self.bottom,
-            left_start_y: self.left_start_y,
-            left_end_y: self.left_end_y,
-            right_start_y: self.right_start_y,
-            right_end_y: self.right_end_y,
-            top_start_x: self.top_start_x,
-            top_end_x: self.top_end_x,
-            bottom_start_x: self.bottom_start_x,
-            bottom_end_x: self.bottom_end_x,
+        if self.edges.is_empty() {
+            return Ok(strut);
         }
-    }
 
-    fn apply_offsets(&mut self) {
-        let x_session = self.x_session.as_ref().unwrap();
-        let (monitor_start_x, monitor_end_x, monitor_start_y, monitor_end_y) = x_session.get_monitor_bounds(self.monitor);
-        let (display_width, display_height) = x_session.display_bounds;
+        let layout = self.layout.as_ref().ok_or(StrutError::NoLayout)?;
 
-        self.left += monitor_start_x;
-        self.right += display_width - monitor_end_x;
-        self.top += monitor_start_y;
-        self.bottom += display_height - monitor_end_y;
-    }
+        if self.monitor >= layout.monitors.len() {
+            return Err(StrutError::NoMonitor(self.monitor));
+        }
 
-    pub fn strut_width(&self, size: Number) -> i32 {
-        match size {
-            Number::Absolute(h) => h,
-            Number::Percent(p) => {
-                let monitor_height = self.x_session
-                    .as_ref()
-                    .unwrap()
-                    .get_monitor_height(self.monitor);
+        let (monitor_start_x, monitor_end_x, monitor_start_y, monitor_end_y) = layout.get_monitor_bounds(self.monitor);
+        let (display_width, display_height) = layout.display_bounds;
+
+        // Struts are measured from the edges of the root window, not of the monitor.
+        let offset = |offset: i32| match self.should_apply_offsets {
+            true => offset,
+            false => 0,
+        };
+
+        for &StrutEdge { side, size, span } in &self.edges {
+            let (thickness, length) = match side {
+                Side::Left | Side::Right => (monitor_end_x - monitor_start_x, monitor_end_y - monitor_start_y),
+                Side::Top | Side::Bottom => (monitor_end_y - monitor_start_y, monitor_end_x - monitor_start_x),
+            };
+
+            let size = match size {
+                Length::Fill => return Err(StrutError::InvalidSize(side, size)),
+                size if size.resolve(thickness, 0) <= 0 => return Err(StrutError::InvalidSize(side, size)),
+                size => size.resolve(thickness, 0),
+            };
+
+            let (start, end) = match span {
+                StrutSpan::Full => (0, length),
+                StrutSpan::Partial(start, end) => (start.resolve(length, 0), end.resolve(length, 0)),
+            };
+
+            if start < 0 || start >= end || end > length {
+                return Err(StrutError::InvalidSpan { side, start, end, length });
+            }
 
-                monitor_height * p
+            // The distance between the edge of the root window and the edge of the monitor.
+            let (edge_offset, span_offset) = match side {
+                Side::Left => (monitor_start_x, monitor_start_y),
+                Side::Right => (display_width - monitor_end_x, monitor_start_y),
+                Side::Top => (monitor_start_y, monitor_start_x),
+                Side::Bottom => (display_height - monitor_end_y, monitor_start_x),
+            };
+
+            let stacked = self.reserved(side, edge_offset, (start + span_offset, end - 1 + span_offset));
+            let size = size + stacked;
+            strut.offset = stacked;
+
+            // The end coordinates of _NET_WM_STRUT_PARTIAL are inclusive.
+            match side {
+                Side::Left => {
+                    strut.left = size + offset(monitor_start_x);
+                    strut.left_start_y = start + offset(monitor_start_y);
+                    strut.left_end_y = end - 1 + offset(monitor_start_y);
+                },
+                Side::Right => {
+                    strut.right = size + offset(display_width - monitor_end_x);
+                    strut.right_start_y = start + offset(monitor_start_y);
+                    strut.right_end_y = end - 1 + offset(monitor_start_y);
+                },
+                Side::Top => {
+                    strut.top = size + offset(monitor_start_y);
+                    strut.top_start_x = start + offset(monitor_start_x);
+                    strut.top_end_x = end - 1 + offset(monitor_start_x);
+                },
+                Side::Bottom => {
+                    strut.bottom = size + offset(display_height - monitor_end_y);
+                    strut.bottom_start_x = start + offset(monitor_start_x);
+                    strut.bottom_end_x = end - 1 + offset(monitor_start_x);
+                },
             }
         }
+
+        Ok(strut)
     }
 
-    pub fn strut_length(&self, size: Number) -> (i32, i32) {
-        todo!()
+    /// How far existing struts on `side` reach into the monitor, where they overlap the root window
+    /// coordinates `span`. `edge_offset` is the distance between the edges of the root window and the monitor.
+    fn reserved(&self, side: Side, edge_offset: i32, span: (i32, i32)) -> i32 {
+        self.existing_struts
+            .iter()
+            .map(|strut| strut.edge(side))
+            .filter(|(thickness, start, end)| *thickness > 0 && *start <= span.1 && span.0 <= *end)
+            .map(|(thickness, _, _)| thickness - edge_offset)
+            .max()
+            .unwrap_or(0)
+            .max(0)
     }
 
-    pub fn xsession(mut self, x_session: Rc<XSessionContext>) -> Self {
-        self.x_session = Some(x_session);
+    pub fn layout(mut self, layout: MonitorLayout) -> Self {
+        self.layout = Some(layout);
         self
     }
 
@@ -190,109 +336,135 @@ impl StrutPartialDefBuilder {
         self
     }
 
-    // TODO: Support percentages
-    pub fn size(mut self, size: i32) -> Self {
-        self.size = size;
+    pub fn existing_struts(mut self, struts: Vec<StrutPartialDef>) -> Self {
+        self.existing_struts = struts;
         self
     }
 
-    pub fn top(mut self, size: Number) -> Self {
-        self.top = self.strut_width(size);
-        self
+    /// Starts reserving space on `side`. Setting a side twice keeps the last one.
+    pub fn side(self, side: Side) -> StrutPartialDefBuilderIntSize {
+        StrutPartialDefBuilderIntSize::new(self, side)
     }
+}
 
-    pub fn bottom(mut self, size: Number) -> Self {
-        self.bottom = self.strut_width(size);
-        self
+impl StrutPartialDefBuilderIntSize {
+    fn new(prev_builder: StrutPartialDefBuilder, side: Side) -> Self {
+        Self {
+            side,
+            prev_builder,
+        }
     }
 
-    pub fn left(mut self, size: Number) -> Self {
-        self.left = self.strut_width(size);
-        self
+    /// The thickness of the strut, in pixels or relative to the monitor.
+    pub fn size(self, size: impl Into<Length>) -> StrutPartialDefBuilderIntLength {
+        StrutPartialDefBuilderIntLength::new(self.prev_builder, self.side, size.into())
     }
+}
 
-    pub fn right(mut self, size: Number) -> Self {
-        self.right = self.strut_width(size);
-        self
+impl StrutPartialDefBuilderIntLength {
+    fn new(prev_builder: StrutPartialDefBuilder, side: Side, size: Length) -> Self {
+        Self {
+            side,
+            size,
+            prev_builder,
+        }
     }
 
-    pub fn full_length(mut self, side: Side) -> Self {
-        let monitor_width = self.x_session
-            .as_ref()
-            .unwrap()
-            .get_monitor_width(self.monitor);
-        let monitor_height = self.x_session
-            .as_ref()
-            .unwrap()
-            .get_monitor_height(self.monitor);
-
-        match side {
-            Side::Left => {
-                self.left = self.size;
-                self.left_end_y = monitor_height;
-            },
-            Side::Right => {
-                self.right = self.size;
-                self.right_end_y = monitor_height;
-            },
-            Side::Top => {
-                self.top = self.size;
-                self.top_end_x = monitor_width;
-            },
-            Side::Bottom => {
-                self.bottom = self.size;
-                self.bottom_end_x = monitor_width;
-            }
-        }
+    /// Reserves the whole edge of the monitor.
+    pub fn full_length(self) -> StrutPartialDefBuilder {
+        self.with_span(StrutSpan::Full)
+    }
 
-        self.non_zero_value_set = true;
-        self
+    /// Reserves the edge from `start` to `end`, relative to the monitor.
+    pub fn partial_length(self, start: impl Into<Length>, end: impl Into<Length>) -> StrutPartialDefBuilder {
+        self.with_span(StrutSpan::Partial(start.into(), end.into()))
     }
 
-    pub fn partial_length(mut self, start: i32, end: i32) -> Self {
-        self.top_start_x = start;
-        self.top_end_x = end;
-        self
+    fn with_span(self, span: StrutSpan) -> StrutPartialDefBuilder {
+        let mut builder = self.prev_builder;
+
+        builder.edges.retain(|edge| edge.side != self.side);
+        builder.edges.push(StrutEdge { side: self.side, size: self.size, span });
+        builder
     }
 }
 
-struct StrutPartialDefBuilderIntSize {
-    side: Side,
-    size: i32,
-    length: (i32, i32),
-    prev_builder: StrutPartialDefBuilder,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x::x::offset_layout;
 
-impl StrutPartialDefBuilderIntSize {
-    fn new(prev_builder: StrutPartialDefBuilder, side: Side, size: i32) -> Self {
-        Self {
-            side,
-            size,
-            length: (0, 0),
-            prev_builder,
-        }
+    fn builder(monitor: usize) -> StrutPartialDefBuilder {
+        StrutPartialDef::builder()
+            .layout(offset_layout())
+            .monitor(monitor)
     }
 
-    fn full_length(mut self) -> StrutPartialDefBuilder {
-        todo!()
+    fn existing_top(monitor: usize) -> StrutPartialDef {
+        builder(monitor).side(Side::Top).size(30).full_length().build().unwrap()
     }
 
-    fn partial_length(mut self) -> StrutPartialDefBuilder {
-        todo!()
+    #[test]
+    fn struts_on_the_primary_monitor() {
+        let top = builder(0).side(Side::Top).size(30).full_length().build().unwrap();
+        assert_eq!(top, StrutPartialDef { top: 230, top_start_x: 0, top_end_x: 1919, ..Default::default() });
+
+        let bottom = builder(0).side(Side::Bottom).size(30).full_length().build().unwrap();
+        assert_eq!(bottom, StrutPartialDef { bottom: 190, bottom_start_x: 0, bottom_end_x: 1919, ..Default::default() });
+
+        let left = builder(0).side(Side::Left).size(40).full_length().build().unwrap();
+        assert_eq!(left, StrutPartialDef { left: 40, left_start_y: 200, left_end_y: 1279, ..Default::default() });
     }
-}
 
-struct StrutPartialDefBuilderIntLength {
-    side: Option<Side>,
-    size: Option<i32>,
-    length: (i32, i32),
-    prev_builder: StrutPartialDefBuilder,
-}
+    #[test]
+    fn struts_on_the_offset_monitor() {
+        let top = builder(1).side(Side::Top).size(30).partial_length(Length::Percent(50.0), Length::Fill).build().unwrap();
+        assert_eq!(top, StrutPartialDef { top: 30, top_start_x: 3200, top_end_x: 4479, ..Default::default() });
 
-impl StrutPartialDefBuilderIntLength {
-    fn new(prev_builder: StrutPartialDefBuilder) -> Self {
-        todo!()
+        let left = builder(1).side(Side::Left).size("10%".parse::<Length>().unwrap()).full_length().build().unwrap();
+        assert_eq!(left, StrutPartialDef { left: 2176, left_start_y: 0, left_end_y: 1439, ..Default::default() });
+
+        let relative = builder(1).dont_apply_offsets().side(Side::Top).size(30).full_length().build().unwrap();
+        assert_eq!(relative, StrutPartialDef { top: 30, top_start_x: 0, top_end_x: 2559, ..Default::default() });
     }
 
+    #[test]
+    fn struts_stack_against_existing_ones() {
+        let strut = builder(0)
+            .existing_struts(vec![existing_top(0)])
+            .side(Side::Top)
+            .size(20)
+            .full_length()
+            .build()
+            .unwrap();
+
+        assert_eq!(strut.offset(), 30);
+        assert_eq!(strut.top, 250);
+
+        // The offset monitor does not overlap the strut of the primary one.
+        let other = builder(1)
+            .existing_struts(vec![existing_top(0)])
+            .side(Side::Top)
+            .size(20)
+            .full_length()
+            .build()
+            .unwrap();
+
+        assert_eq!(other.offset(), 0);
+    }
 
+    #[test]
+    fn invalid_struts() {
+        assert!(matches!(builder(2).side(Side::Top).size(30).full_length().build(), Err(StrutError::NoMonitor(2))));
+        assert!(matches!(builder(0).side(Side::Top).size(Length::Fill).full_length().build(), Err(StrutError::InvalidSize(Side::Top, _))));
+        assert!(matches!(builder(0).side(Side::Top).size(0).full_length().build(), Err(StrutError::InvalidSize(Side::Top, _))));
+        assert!(matches!(
+            builder(0).side(Side::Top).size(30).partial_length(Length::Fill, Length::Percent(50.0)).build(),
+            Err(StrutError::InvalidSpan { start: 1920, end: 960, length: 1920, .. })
+        ));
+        assert!(matches!(
+            StrutPartialDef::builder().side(Side::Top).size(30).full_length().build(),
+            Err(StrutError::NoLayout)
+        ));
+    }
 }
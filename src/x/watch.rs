@@ -0,0 +1,93 @@
+use std::thread::{self, JoinHandle};
+
+use anyhow::Result;
+use tracing::{debug, error};
+use x11rb::connection::Connection;
+use x11rb::protocol::Event as XEvent;
+use x11rb::protocol::xproto::{ChangeWindowAttributesAux, ConnectionExt, EventMask, Window};
+use x11rb::rust_connection::RustConnection;
+
+use crate::event::{EVENT_MANAGER, Event};
+use crate::state::{HorizonWm, WM};
+use crate::x::ewmh::AtomCollection;
+
+/// Keeps [`WM`] in sync with the X server from `PropertyNotify` events on the root window and every client,
+/// publishing [`Event::HorizonWmUpdated`] after each change, and [`Event::StrutsUpdated`] when a client
+/// reserving space appears, goes away or changes its strut.
+///
+/// Uses its own X connection, since events are read on a dedicated thread.
+pub fn spawn_wm_watcher() -> JoinHandle<()> {
+    thread::spawn(|| {
+        if let Err(err) = watch_wm() {
+            error!("Stopped following the window manager state: {err}");
+        }
+    })
+}
+
+fn watch_wm() -> Result<()> {
+    let (connection, screen_idx) = RustConnection::connect(None)?;
+    let root = connection.setup().roots[screen_idx].root;
+    let atoms = AtomCollection::new(&connection)?.reply()?;
+
+    select_property_changes(&connection, root)?;
+
+    let mut wm = HorizonWm::from_ewmh(&connection, &atoms, root)?;
+    for window in wm.take_new_windows() {
+        // Clients destroyed since _NET_CLIENT_LIST was read are dropped with its next change.
+        if let Err(err) = select_property_changes(&connection, window) {
+            debug!("Not following window {window}: {err}");
+        }
+    }
+
+    *WM.write().unwrap() = wm;
+    EVENT_MANAGER.notify_listeners(Event::HorizonWmUpdated);
+    EVENT_MANAGER.notify_listeners(Event::StrutsUpdated);
+
+    loop {
+        let XEvent::PropertyNotify(event) = connection.wait_for_event()? else {
+            continue;
+        };
+
+        // Struts only change with the strut of a client, or with the clients themselves.
+        let affects_struts = event.atom == atoms._NET_WM_STRUT_PARTIAL
+            || event.atom == atoms._NET_WM_STRUT
+            || event.atom == atoms._NET_CLIENT_LIST;
+
+        let (changed, struts_changed, new_windows) = {
+            let mut wm = WM.write().unwrap();
+            let struts = affects_struts.then(|| wm.struts());
+            let changed = match wm.update_property(&connection, &atoms, root, event.window, event.atom) {
+                Ok(changed) => changed,
+                // Usually a client destroyed in the meantime. The change may have been applied in part,
+                // so it is still published.
+                Err(err) => {
+                    debug!("Failed to update property {} of window {}: {err}", event.atom, event.window);
+                    true
+                },
+            };
+            let struts_changed = changed && struts.is_some_and(|struts| wm.struts() != struts);
+
+            (changed, struts_changed, wm.take_new_windows())
+        };
+
+        for window in new_windows {
+            // The window may already be gone again, which is fine.
+            let _ = select_property_changes(&connection, window);
+        }
+
+        if changed {
+            EVENT_MANAGER.notify_listeners(Event::HorizonWmUpdated);
+        }
+
+        if struts_changed {
+            EVENT_MANAGER.notify_listeners(Event::StrutsUpdated);
+        }
+    }
+}
+
+fn select_property_changes(connection: &RustConnection, window: Window) -> Result<()> {
+    let attributes = ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE);
+
+    connection.change_window_attributes(window, &attributes)?.check()?;
+    Ok(())
+}
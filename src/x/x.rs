@@ -1,75 +1,232 @@
-use std::cmp::max;
+use std::collections::HashSet;
+use std::panic;
 use std::rc::Rc;
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
 
 use gdk_x11::prelude::*;
-use gdk_x11::{X11Monitor, X11Surface};
+use gdk_x11::X11Surface;
 use gdk_x11::gdk::Display;
 use gtk::prelude::*;
+use once_cell::sync::Lazy;
+use tracing::{debug, error, warn};
 use x11rb::connection::Connection;
-use x11rb::protocol::xproto::{Atom, AtomEnum, ConfigureWindowAux, ConnectionExt, PropMode};
+use x11rb::errors::ReplyError;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{Atom, AtomEnum, ConfigureWindowAux, ConnectionExt, PropMode, Window};
 use x11rb::rust_connection::RustConnection;
+use x11rb::wrapper::ConnectionExt as _;
 
 use crate::horizon::{HorizonWindow, HorizonWindowConfig};
+use crate::util::Side;
 use crate::x::ewmh::{AtomCollection, WindowType, EwmhHints};
-use crate::x::strut::StrutPartialDef; 
+use crate::x::strut::StrutPartialDef;
+
+/// Windows that currently have a strut set, so the panic hook can clear them without the contexts,
+/// which live on the GTK thread.
+static STRUT_WINDOWS: Lazy<Mutex<HashSet<Window>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// The geometry of a monitor in root window coordinates, as reported by RandR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MonitorGeometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// The size of the root window and the monitors placed on it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonitorLayout {
+    /// The size of the root window, which struts are measured from.
+    pub display_bounds: (i32, i32),
+    pub monitors: Vec<MonitorGeometry>,
+}
+
+impl MonitorLayout {
+    pub fn new(display_bounds: (i32, i32), monitors: Vec<MonitorGeometry>) -> Self {
+        Self { display_bounds, monitors }
+    }
+
+    pub fn get_monitor_offsets(&self, monitor_index: usize) -> (i32, i32) {
+        let monitor = &self.monitors[monitor_index];
+        (monitor.x, monitor.y)
+    }
+
+    pub fn get_monitor_bounds(&self, monitor_index: usize) -> (i32, i32, i32, i32) {
+        let monitor = &self.monitors[monitor_index];
+
+        // (start_x, end_x, start_y, end_y)
+        (
+            monitor.x, monitor.x + monitor.width,
+            monitor.y, monitor.y + monitor.height
+        )
+    }
+
+    pub fn get_monitor_width(&self, monitor_index: usize) -> i32 {
+        self.monitors[monitor_index].width
+    }
+
+    pub fn get_monitor_height(&self, monitor_index: usize) -> i32 {
+        self.monitors[monitor_index].height
+    }
+}
+
+/// A 1920x1080 monitor with a 2560x1440 one to its right, whose top is 200 pixels higher.
+#[cfg(test)]
+pub(crate) fn offset_layout() -> MonitorLayout {
+    MonitorLayout::new((4480, 1440), vec![
+        MonitorGeometry { x: 0, y: 200, width: 1920, height: 1080 },
+        MonitorGeometry { x: 1920, y: 0, width: 2560, height: 1440 },
+    ])
+}
 
 #[derive(Debug)]
 pub struct XSessionContext {
     pub connection: Rc<RustConnection>,
+    pub root: Window,
+    pub atoms: AtomCollection,
     pub display: Display,
-    pub display_bounds: (i32, i32),
-    pub monitors: Vec<X11Monitor>,
+    pub layout: MonitorLayout,
 }
 
 impl XSessionContext {
     pub fn new() -> Self {
         let (connection, screen_idx) = RustConnection::connect(None).unwrap();
+        let screen = &connection.setup().roots[screen_idx];
+        let root = screen.root;
+        let display_bounds = (screen.width_in_pixels as i32, screen.height_in_pixels as i32);
+        let atoms = AtomCollection::new(&connection).unwrap()
+            .reply().unwrap();
         let display = Display::default().unwrap();
-        let monitors: Vec<_> = display.monitors()
-            .into_iter()
-            .map(|x| x.unwrap().downcast::<X11Monitor>().unwrap())
-            .collect();
-
-        let mut display_width = 0;
-        let mut display_height = 0;
-
-        for monitor in &monitors {
-            let end_x = monitor.workarea().x() + monitor.workarea().width();
-            let end_y = monitor.workarea().y() + monitor.workarea().height();
-            display_width = max(display_width, end_x);
-            display_height = max(display_height, end_y);
-        }
+
+        // NOTE: The GDK workarea already excludes the struts of running docks, including our own,
+        // so the monitor geometry comes from RandR instead.
+        let monitors = match query_monitors(&connection, root) {
+            Ok(monitors) if !monitors.is_empty() => monitors,
+            Ok(_) => {
+                warn!("RandR reports no active monitors, using the whole screen");
+                vec![screen_geometry(display_bounds)]
+            },
+            Err(err) => {
+                warn!("Failed to query monitors through RandR, using the whole screen: {err}");
+                vec![screen_geometry(display_bounds)]
+            },
+        };
 
         Self {
             connection: connection.into(),
+            root,
+            atoms,
             display,
-            display_bounds: (display_width, display_height),
-            monitors,
+            layout: MonitorLayout::new(display_bounds, monitors),
         }
     }
 
-    pub fn get_monitor_offsets(&self, monitor_index: usize) -> (i32, i32) {
-        let workarea = self.monitors[monitor_index].workarea();
-        (workarea.x(), workarea.y())
+    /// Removes `_NET_WM_STRUT_PARTIAL` from `window`, giving back the space it reserved.
+    ///
+    /// Only logs failures, since this also runs while shutting down, when the window may already be gone.
+    pub fn delete_strut(&self, window: Window) {
+        STRUT_WINDOWS.lock().unwrap_or_else(|err| err.into_inner()).remove(&window);
+
+        let deleted = self.connection
+            .delete_property(window, self.atoms._NET_WM_STRUT_PARTIAL)
+            .map_err(ReplyError::from)
+            .and_then(|cookie| cookie.check());
+
+        if let Err(err) = deleted {
+            warn!("Failed to delete _NET_WM_STRUT_PARTIAL of window {window}: {err}");
+        }
+
+        let _ = self.connection.flush();
     }
+}
 
-    pub fn get_monitor_bounds(&self, monitor_index: usize) -> (i32, i32, i32, i32) {
-        let workarea = self.monitors[monitor_index].workarea();
+/// Clears the struts of all windows when the GTK thread `gtk_thread` panics, before running the default panic hook.
+///
+/// Panics on other threads leave the bars running, so their struts are kept.
+/// Uses a new X connection, as the one of the [`XSessionContext`] belongs to the GTK thread.
+pub fn clear_struts_on_panic(gtk_thread: ThreadId) {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        if thread::current().id() == gtk_thread {
+            let windows = STRUT_WINDOWS.lock().unwrap_or_else(|err| err.into_inner()).clone();
+
+            if !windows.is_empty() {
+                if let Err(err) = delete_struts(&windows) {
+                    error!("Failed to clear struts: {err}");
+                }
+            }
+        }
 
-        // (start_x, end_x, start_y, end_y)
-        (
-            workarea.x(), workarea.x() + workarea.width(),
-            workarea.y(), workarea.y() + workarea.height()
-        )
+        default_hook(info);
+    }));
+}
+
+fn delete_struts(windows: &HashSet<Window>) -> anyhow::Result<()> {
+    let (connection, _) = RustConnection::connect(None)?;
+    let atoms = AtomCollection::new(&connection)?.reply()?;
+
+    for window in windows {
+        connection.delete_property(*window, atoms._NET_WM_STRUT_PARTIAL)?;
     }
 
-    pub fn get_monitor_width(&self, monitor_index: usize) -> i32 {
-        self.monitors[monitor_index].workarea().width()
+    connection.flush()?;
+    Ok(())
+}
+
+/// The active monitors, in the order RandR (and so GDK) lists them.
+///
+/// Uses the monitors of RandR 1.5 when the server has them, otherwise the enabled CRTCs.
+fn query_monitors(connection: &RustConnection, root: Window) -> Result<Vec<MonitorGeometry>, ReplyError> {
+    let version = connection.randr_query_version(1, 5)?.reply()?;
+
+    if (version.major_version, version.minor_version) >= (1, 5) {
+        let monitors = connection.randr_get_monitors(root, true)?.reply()?.monitors
+            .iter()
+            .map(|monitor| MonitorGeometry {
+                x: monitor.x as i32,
+                y: monitor.y as i32,
+                width: monitor.width as i32,
+                height: monitor.height as i32,
+            })
+            .collect::<Vec<_>>();
+
+        if !monitors.is_empty() {
+            return Ok(monitors);
+        }
     }
 
-    pub fn get_monitor_height(&self, monitor_index: usize) -> i32 {
-        self.monitors[monitor_index].workarea().height()
+    let resources = connection.randr_get_screen_resources_current(root)?.reply()?;
+    let mut monitors = Vec::new();
+
+    for crtc in resources.crtcs {
+        let info = connection.randr_get_crtc_info(crtc, resources.config_timestamp)?.reply()?;
+
+        // Disabled CRTCs have no mode and no outputs.
+        if info.mode == 0 || info.outputs.is_empty() {
+            continue;
+        }
+
+        let monitor = MonitorGeometry {
+            x: info.x as i32,
+            y: info.y as i32,
+            width: info.width as i32,
+            height: info.height as i32,
+        };
+
+        // Mirrored outputs are driven by CRTCs with the same geometry.
+        if !monitors.contains(&monitor) {
+            monitors.push(monitor);
+        }
     }
+
+    Ok(monitors)
+}
+
+fn screen_geometry((width, height): (i32, i32)) -> MonitorGeometry {
+    MonitorGeometry { x: 0, y: 0, width, height }
 }
 
 pub struct XWindowContext {
@@ -80,7 +237,8 @@ pub struct XWindowContext {
 }
 
 impl XWindowContext {
-    pub fn new(x_session: Rc<XSessionContext>, horizon_window: &HorizonWindow) -> Self {
+    /// `existing` are the struts of other docks, which the strut and position of the window are stacked against.
+    pub fn new(x_session: Rc<XSessionContext>, horizon_window: &HorizonWindow, existing: &[StrutPartialDef]) -> Self {
         let surface = horizon_window.gtk_window
             .surface()
             .and_downcast::<X11Surface>()
@@ -88,10 +246,19 @@ impl XWindowContext {
 
         let xid = surface.xid() as Atom;
 
-        let atoms = AtomCollection::new(&x_session.connection).unwrap()
-            .reply().unwrap();
+        let atoms = x_session.atoms;
+
+        let strut = horizon_window.config.strut
+            .as_ref()
+            .and_then(|strut| match strut.build(&x_session.layout, horizon_window.config.screen, existing) {
+                Ok(strut) => Some(strut),
+                Err(err) => {
+                    error!("Not reserving space for window {}: {err}", horizon_window.name);
+                    None
+                },
+            });
 
-        let ewmh = EwmhHints::new(&atoms, &horizon_window.config.window_type, &horizon_window.config.strut);
+        let ewmh = EwmhHints::new(&atoms, &horizon_window.config.window_type, &strut);
 
         Self {
             surface,
@@ -108,21 +275,38 @@ impl XWindowContext {
     }
 
     pub fn move_window(&self, x_session: Rc<XSessionContext>, horizon_window: &HorizonWindowConfig) {
-        let (monitor_start_x, monitor_start_y) = x_session.get_monitor_offsets(horizon_window.screen);
+        let mut geometry = horizon_window.geometry(&x_session.layout);
+
+        // Moved inward past the space other docks reserve on the same edge.
+        if let (Some(config), Some(strut)) = (&horizon_window.strut, &self.ewmh.strut) {
+            match config.side {
+                Side::Left => geometry.x += strut.offset(),
+                Side::Right => geometry.x -= strut.offset(),
+                Side::Top => geometry.y += strut.offset(),
+                Side::Bottom => geometry.y -= strut.offset(),
+            }
+        }
 
         let window_config = ConfigureWindowAux::new()
-            .x(monitor_start_x as i32 + horizon_window.position.x)
-            .y(monitor_start_y as i32 + horizon_window.position.y);
+            .x(geometry.x)
+            .y(geometry.y);
             // .width(horizon_window.size.width as u32)
             // .height(horizon_window.size.height);
 
         let _ = x_session.connection.configure_window(self.xid, &window_config).unwrap();
         x_session.connection.flush().expect("Failed to configure X window.");
-        dbg!("{:?}", window_config);
+        debug!("Moved window {} to {}, {}", self.xid, geometry.x, geometry.y);
+    }
+
+    /// The strut set on the window, if any.
+    pub fn strut(&self) -> Option<&StrutPartialDef> {
+        self.ewmh.strut.as_ref()
     }
 
     pub fn set_ewmh_hints(&self, x_session: Rc<XSessionContext>, horizon_window: &HorizonWindow) {
         self.set_window_type_hint(x_session.clone());
+        // Before the strut, so other horizon processes know not to stack against it once they see the strut.
+        self.set_horizon_window_hint(x_session.clone());
         self.set_strut_partial_hint(x_session.clone());
 
         // NOTE: Eventually will create our own methods like set_strut_partial_hint() rather than use gtk's builtins.
@@ -132,17 +316,23 @@ impl XWindowContext {
     }
 
     fn set_strut_partial_hint(&self, x_session: Rc<XSessionContext>) {
-        if self.ewmh.strut.is_none() {
+        // A strut removed from the config, or one that failed to build, must not keep its old space.
+        let Some(strut) = &self.ewmh.strut else {
+            self.reset_strut_partial_hint(x_session);
             return;
-        }
+        };
 
-        let net_wm_strut_partial = x_session.connection.intern_atom(false, b"_NET_WM_STRUT_PARTIAL")
-            .expect("Failed to intern _NET_WM_STRUT_PARTIAL")
-            .reply()
-            .expect("Failed to get _NET_WM_STRUT_PARTIAL atom");
+        // Failing here must not panic the GTK thread, the window just does not reserve its space.
+        if let Err(err) = self.write_strut_partial(&x_session, strut) {
+            error!("Failed to set _NET_WM_STRUT_PARTIAL on window {}: {err}", self.xid);
+            return;
+        }
 
-        let strut = self.ewmh.strut.as_ref().unwrap().as_x11_ready_value();
+        STRUT_WINDOWS.lock().unwrap_or_else(|err| err.into_inner()).insert(self.xid);
+        debug!("Set _NET_WM_STRUT_PARTIAL on window {}", self.xid);
+    }
 
+    fn write_strut_partial(&self, x_session: &XSessionContext, strut: &StrutPartialDef) -> anyhow::Result<()> {
         x_session.connection.change_property(
             PropMode::REPLACE,
             self.xid,
@@ -150,29 +340,30 @@ impl XWindowContext {
             AtomEnum::CARDINAL,
             32,
             12,
-            &strut
-        ).expect("Failed to set _NET_WM_STRUT_PARTIAL property").check().unwrap();
+            &strut.as_x11_ready_value(),
+        )?.check()?;
 
-        x_session.connection.flush().expect("Failed to flush connection");
-        println!("Set _NET_WM_STRUT_PARTIAL");
+        x_session.connection.flush()?;
+        Ok(())
     }
 
-    fn reset_strut_partial_hint(&self, x_session: Rc<XSessionContext>) {
-        let zero_strut = StrutPartialDef::default().as_x11_ready_value();
+    pub fn reset_strut_partial_hint(&self, x_session: Rc<XSessionContext>) {
+        x_session.delete_strut(self.xid);
+    }
 
-        // NOTE: use delete_property()
-        x_session.connection.change_property(
+    /// Tags the window as a bar of horizon with `_HORIZON_WINDOW`.
+    fn set_horizon_window_hint(&self, x_session: Rc<XSessionContext>) {
+        let result = x_session.connection.change_property32(
             PropMode::REPLACE,
             self.xid,
-            self.atoms._NET_WM_STRUT_PARTIAL,
+            self.atoms._HORIZON_WINDOW,
             AtomEnum::CARDINAL,
-            32,
-            12,
-            &zero_strut
-        ).expect("Failed to reset _NET_WM_STRUT_PARTIAL property").check().unwrap();
+            &[1],
+        );
 
-        x_session.connection.flush().expect("Failed to flush connection");
-        // println!("Reset _NET_WM_STRUT_PARTIAL");
+        if let Err(err) = result {
+            error!("Failed to set _HORIZON_WINDOW on window {}: {err}", self.xid);
+        }
     }
 
     fn set_window_type_hint(&self, x_session: Rc<XSessionContext>) {
@@ -190,3 +381,19 @@ impl XWindowContext {
         // println!("Set _NET_WM_WINDOW_TYPE");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn monitor_bounds() {
+        let layout = offset_layout();
+
+        assert_eq!(layout.get_monitor_bounds(0), (0, 1920, 200, 1280));
+        assert_eq!(layout.get_monitor_bounds(1), (1920, 4480, 0, 1440));
+        assert_eq!(layout.get_monitor_offsets(0), (0, 200));
+        assert_eq!(layout.get_monitor_offsets(1), (1920, 0));
+        assert_eq!((layout.get_monitor_width(1), layout.get_monitor_height(1)), (2560, 1440));
+    }
+}
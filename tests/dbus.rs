@@ -0,0 +1,134 @@
+//! Runs a D-Bus provided state against a private `dbus-daemon` session bus.
+
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use horizon::state::{spawn_state, CustomState, CustomStateDef, DbusBus, DbusProperties, Provider};
+use serde_json::{json, Value};
+use zbus::{interface, Connection, ConnectionBuilder};
+
+const DESTINATION: &str = "dev.snxwman.Horizon.Test";
+const PATH: &str = "/dev/snxwman/Horizon/Test";
+const INTERFACE: &str = "dev.snxwman.Horizon.Test";
+
+/// A private session bus, stopped when dropped.
+struct DbusDaemon {
+    child: Child,
+    address: String,
+}
+
+impl DbusDaemon {
+    /// `None` when `dbus-daemon` is not installed.
+    fn start() -> Option<Self> {
+        let mut child = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+
+        let mut address = String::new();
+        BufReader::new(child.stdout.take()?).read_line(&mut address).ok()?;
+
+        Some(Self { child, address: address.trim().to_owned() })
+    }
+}
+
+impl Drop for DbusDaemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+struct Battery {
+    level: u32,
+    status: String,
+}
+
+#[interface(name = "dev.snxwman.Horizon.Test")]
+impl Battery {
+    #[zbus(property)]
+    fn level(&self) -> u32 {
+        self.level
+    }
+
+    #[zbus(property)]
+    fn status(&self) -> String {
+        self.status.clone()
+    }
+}
+
+async fn serve(address: &str, level: u32, status: &str) -> Connection {
+    let battery = Battery { level, status: status.to_owned() };
+
+    ConnectionBuilder::address(address)
+        .and_then(|builder| builder.name(DESTINATION))
+        .and_then(|builder| builder.serve_at(PATH, battery))
+        .expect("Failed to configure the service")
+        .build()
+        .await
+        .expect("Failed to start the service")
+}
+
+/// Waits for the value of `state` to become `expected`.
+async fn expect_value(state: &RwLock<CustomState>, expected: Value) {
+    for _ in 0..100 {
+        if state.read().unwrap().value == expected {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    assert_eq!(state.read().unwrap().value, expected);
+}
+
+#[tokio::test]
+async fn dbus_properties_are_mapped_onto_the_state() {
+    let Some(daemon) = DbusDaemon::start() else {
+        eprintln!("dbus-daemon is not installed, skipping");
+        return;
+    };
+
+    let service = serve(&daemon.address, 80, "Discharging").await;
+
+    let state = Arc::new(RwLock::new(CustomState::new(&CustomStateDef {
+        name: String::from("battery"),
+        provider: Provider::Dbus(DbusProperties {
+            bus: DbusBus::Address(daemon.address.clone()),
+            destination: String::from(DESTINATION),
+            path: String::from(PATH),
+            interface: String::from(INTERFACE),
+        }),
+        interval: None,
+    })));
+
+    let task = spawn_state(state.clone());
+
+    expect_value(&state, json!({ "Level": 80, "Status": "Discharging" })).await;
+
+    // Changes arrive through PropertiesChanged.
+    let battery = service.object_server()
+        .interface::<_, Battery>(PATH)
+        .await
+        .expect("Battery is served");
+
+    battery.get_mut().await.level = 79;
+    battery.get().await
+        .level_changed(battery.signal_context())
+        .await
+        .expect("Failed to emit PropertiesChanged");
+
+    expect_value(&state, json!({ "Level": 79, "Status": "Discharging" })).await;
+
+    // A restarted service is read again, without announcing its values.
+    drop(battery);
+    service.close().await.expect("Failed to stop the service");
+    let _service = serve(&daemon.address, 100, "Full").await;
+
+    expect_value(&state, json!({ "Level": 100, "Status": "Full" })).await;
+
+    task.abort();
+}